@@ -0,0 +1,17 @@
+//! Tells the crate which version of syntect it is built with,
+//! as dumps of compiled syntaxes can only be read by that version.
+
+use std::fs;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let version = lock
+        .split("[[package]]")
+        .find(|package| package.lines().any(|line| line == "name = \"syntect\""))
+        .and_then(|package| package.lines().find_map(|line| line.strip_prefix("version = \"")))
+        .and_then(|version| version.strip_suffix('"'))
+        .unwrap_or("unknown");
+    println!("cargo:rustc-env=SYNTECT_VERSION={version}");
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+/// Writes `contents` unless the file already has them, so that rebuilding
+/// an unchanged site leaves the output untouched, modification times included.
+pub(crate) fn write_if_changed(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), io::Error> {
+    let contents = contents.as_ref();
+    if fs::read(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)
+}
+
+/// Mirrors the files of `from` into `to`, subdirectories included,
+/// skipping files for which `skip` returns true. Returns the files of `to`.
+pub(crate) fn copy_dir(from: &Path, to: &Path, skip: &impl Fn(&Path) -> bool) -> Result<Vec<PathBuf>, io::Error> {
+    let mut copied = Vec::new();
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copied.extend(copy_dir(&path, &target, skip)?);
+        } else if !skip(&path) {
+            write_if_changed(&target, fs::read(&path)?)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", target.display())))?;
+            copied.push(target);
+        }
+    }
+    Ok(copied)
+}
+
+
+/// FNV-1a, for names of files that outlive a build: unlike `DefaultHasher`,
+/// it gives the same hash with every Rust version and on every platform.
+pub(crate) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StableHasher {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_hash_matches_fnv1a() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = StableHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
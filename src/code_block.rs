@@ -0,0 +1,236 @@
+use std::ops::RangeInclusive;
+
+use crate::push_str::{escape_html, push, PushStr};
+
+
+/// What the info string of a fenced code block asks for, like
+/// ```` ```rust,linenos,hl_lines=3-5 8,linenostart=10,name=src/main.rs ````
+#[derive(Default)]
+pub(crate) struct CodeBlockInfo {
+    pub(crate) language: Option<String>,
+    /// Whether to show line numbers in a gutter
+    pub(crate) line_numbers: bool,
+    /// Number of the first line, when showing line numbers
+    pub(crate) start_line: usize,
+    /// Lines to emphasize, counting from 1 regardless of `start_line`
+    pub(crate) highlighted_lines: Vec<RangeInclusive<usize>>,
+    /// File name shown as a caption
+    pub(crate) name: Option<String>,
+}
+
+impl CodeBlockInfo {
+    /// Parses an info string, skipping invalid attributes and reporting them in `errors`.
+    pub(crate) fn parse(info: &str, errors: &mut Vec<String>) -> Self {
+        let mut attributes = split_attributes(info).into_iter();
+        let language = attributes.next().filter(|language| !language.is_empty());
+
+        let mut block = CodeBlockInfo {
+            language: language.map(str::to_owned),
+            start_line: 1,
+            ..Default::default()
+        };
+
+        for attribute in attributes {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(unquote(value.trim()))),
+                None => (attribute, None),
+            };
+
+            match (key, value) {
+                ("", None) => {}
+                ("linenos", None) => block.line_numbers = true,
+                ("linenostart", Some(value)) => match value.parse() {
+                    Ok(start) => block.start_line = start,
+                    Err(_) => errors.push(format!("invalid linenostart {value}")),
+                },
+                ("hl_lines", Some(value)) => {
+                    for range in value.split_whitespace() {
+                        match parse_range(range) {
+                            Some(range) => block.highlighted_lines.push(range),
+                            None => errors.push(format!("invalid line range {range} in hl_lines")),
+                        }
+                    }
+                }
+                ("name", Some(value)) => block.name = Some(value.to_owned()),
+                ("linenos", Some(_)) => errors.push("linenos does not take a value".to_owned()),
+                ("linenostart" | "hl_lines" | "name", None) => {
+                    errors.push(format!("{key} needs a value, like {key}=..."))
+                }
+                _ => errors.push(format!("unknown code block attribute {key}")),
+            }
+        }
+
+        block
+    }
+
+    /// Whether the code must be split into one element per line
+    pub(crate) fn needs_lines(&self) -> bool {
+        self.line_numbers || !self.highlighted_lines.is_empty()
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted_lines.iter().any(|range| range.contains(&line))
+    }
+
+    /// Writes the highlighted `html` of the code with each line in its own
+    /// `<span class='line'>`, with its number in a gutter that is not selected
+    /// nor copied along with the code.
+    ///
+    /// Syntect's spans may cover several lines, like in block comments,
+    /// so they are closed at the end of each line and reopened on the next one.
+    pub(crate) fn write_lines(&self, buf: &mut impl PushStr, html: &str) {
+        // Syntect closes its outermost span after the final newline,
+        // which must not become an empty last line
+        let without_closing = html.trim_end_matches("</span>");
+        let closing = &html[without_closing.len()..];
+        let html = match without_closing.strip_suffix('\n') {
+            Some(code) => format!("{code}{closing}"),
+            None => html.to_owned(),
+        };
+
+        let mut open_spans: Vec<&str> = Vec::new();
+        let lines = html.split('\n');
+
+        for (i, line) in lines.enumerate() {
+            if self.is_highlighted(i + 1) {
+                buf.push_str("<span class='line hl'>");
+            } else {
+                buf.push_str("<span class='line'>");
+            }
+            if self.line_numbers {
+                push!(
+                    buf,
+                    "<span class='lineno' aria-hidden='true' data-line='{}'></span>",
+                    self.start_line + i,
+                );
+            }
+
+            for span in &open_spans {
+                buf.push_str(span);
+            }
+
+            let mut rest = line;
+            while let Some(start) = rest.find('<') {
+                let Some(end) = rest[start..].find('>').map(|end| start + end + 1) else {
+                    break;
+                };
+                let tag = &rest[start..end];
+                if tag == "</span>" {
+                    open_spans.pop();
+                } else if tag.starts_with("<span") {
+                    open_spans.push(tag);
+                }
+                rest = &rest[end..];
+            }
+            buf.push_str(line);
+
+            for _ in &open_spans {
+                buf.push_str("</span>");
+            }
+            buf.push_str("\n</span>");
+        }
+    }
+
+    /// Writes the `<figure>` around the block, with a caption holding its
+    /// name and the name of its language, if any.
+    /// The language is also kept in `data-language` for scripts and styles.
+    pub(crate) fn write_figure_start(&self, buf: &mut impl PushStr, language: Option<&str>) {
+        buf.push_str("<figure class='code'");
+        if let Some(language) = language {
+            buf.push_str(" data-language='");
+            escape_html(buf, language);
+            buf.push_str("'");
+        }
+        buf.push_str(">");
+
+        if self.name.is_none() && language.is_none() {
+            return;
+        }
+        buf.push_str("<figcaption class='code-header'>");
+        if let Some(name) = &self.name {
+            buf.push_str("<span class='code-name'>");
+            escape_html(buf, name);
+            buf.push_str("</span>");
+        }
+        if let Some(language) = language {
+            buf.push_str("<span class='code-language'>");
+            escape_html(buf, language);
+            buf.push_str("</span>");
+        }
+        buf.push_str("</figcaption>");
+    }
+}
+
+/// Splits on commas, except inside quoted values
+fn split_attributes(info: &str) -> Vec<&str> {
+    let mut attributes = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+
+    for (i, c) in info.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                attributes.push(info[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    attributes.push(info[start..].trim());
+
+    attributes
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parses `3` or `3-5`
+fn parse_range(range: &str) -> Option<RangeInclusive<usize>> {
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (start <= end).then_some(start..=end)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_strings() {
+        let mut errors = Vec::new();
+        let info = CodeBlockInfo::parse("rust,linenos,hl_lines=3-5 8,linenostart=10,name=\"src/a,b.rs\"", &mut errors);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(info.language.as_deref(), Some("rust"));
+        assert!(info.line_numbers);
+        assert_eq!(info.start_line, 10);
+        assert_eq!(info.highlighted_lines, vec![3..=5, 8..=8]);
+        assert_eq!(info.name.as_deref(), Some("src/a,b.rs"));
+
+        let info = CodeBlockInfo::parse("", &mut errors);
+        assert_eq!(info.language, None);
+        assert_eq!(info.start_line, 1);
+        assert!(!info.needs_lines());
+    }
+
+    #[test]
+    fn invalid_attributes() {
+        let cases = [
+            ("rust,linenostart=x", "invalid linenostart x"),
+            ("rust,hl_lines=5-3", "invalid line range 5-3 in hl_lines"),
+            ("rust,linenos=1", "linenos does not take a value"),
+            ("rust,name", "name needs a value, like name=..."),
+            ("rust,wrap", "unknown code block attribute wrap"),
+        ];
+        for (info, error) in cases {
+            let mut errors = Vec::new();
+            CodeBlockInfo::parse(info, &mut errors);
+            assert_eq!(errors, vec![error.to_owned()], "{info:?}");
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::images::ImageSettings;
+use crate::links::ExternalLinks;
+
+
+/// Site-wide settings, read from `config.yaml`.
+/// Every field is optional and falls back to its default.
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    /// Where the site is published, like `https://example.com`
+    pub(crate) base_url: String,
+    /// Folder of the markdown sources
+    pub(crate) content_dir: PathBuf,
+    /// Folder copied as is to the output, for files like `favicon.ico`
+    pub(crate) static_dir: PathBuf,
+    /// Whether to write posts as `slug/index.html`, to be linked as `slug/`,
+    /// instead of `slug.html`
+    pub(crate) pretty_urls: bool,
+    /// Average reading speed for prose
+    pub(crate) words_per_minute: usize,
+    /// Reading speed for code blocks, which are read much slower than prose
+    pub(crate) code_words_per_minute: usize,
+    /// Maximum length of summaries, in characters
+    pub(crate) summary_length: usize,
+    /// Commands rendering code blocks of a language to SVG, as the program
+    /// and its arguments, reading the code on stdin and writing the SVG to stdout
+    pub(crate) code_block_commands: HashMap<String, Vec<String>>,
+    /// Name of the code theme in `templates/code_themes` used with a light color scheme
+    pub(crate) code_theme_light: String,
+    /// Name of the code theme used with a dark color scheme
+    pub(crate) code_theme_dark: String,
+    /// Folder of `.sublime-syntax` files added to the default syntaxes
+    pub(crate) syntaxes_dir: PathBuf,
+    /// Other names for languages of code blocks, like `shell` for `bash`
+    pub(crate) language_aliases: HashMap<String, String>,
+    /// Resized variants of images
+    pub(crate) images: ImageSettings,
+    /// How to render and check external links
+    pub(crate) external_links: ExternalLinks,
+    /// Whether to add a button copying the code of code blocks to posts
+    pub(crate) copy_code_button: bool,
+    /// Front matter fields grouping posts, each getting a page per term
+    pub(crate) taxonomies: Vec<String>,
+    /// Number of posts per page of listings, 0 to never split them
+    pub(crate) paginate_by: usize,
+    /// Number of related posts shown under each post
+    pub(crate) related_posts: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: String::new(),
+            content_dir: PathBuf::from("markdown"),
+            static_dir: PathBuf::from("static"),
+            pretty_urls: false,
+            words_per_minute: 200,
+            code_words_per_minute: 80,
+            summary_length: 280,
+            code_block_commands: HashMap::new(),
+            code_theme_light: "light".to_owned(),
+            code_theme_dark: "dark".to_owned(),
+            syntaxes_dir: PathBuf::from("templates/syntaxes"),
+            language_aliases: HashMap::from([
+                ("shell".to_owned(), "bash".to_owned()),
+                ("console".to_owned(), "bash".to_owned()),
+            ]),
+            images: ImageSettings::default(),
+            external_links: ExternalLinks::default(),
+            copy_code_button: false,
+            taxonomies: vec!["tags".to_owned(), "categories".to_owned()],
+            paginate_by: 10,
+            related_posts: 3,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn load(path: &Path) -> Result<Self, io::Error> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        serde_yaml::from_str(&contents).map_err(io::Error::other)
+    }
+}
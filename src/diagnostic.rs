@@ -0,0 +1,26 @@
+/// A problem found while rendering a post, pointing into its markdown source.
+pub(crate) struct Diagnostic {
+    /// Byte offset in the markdown source
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(offset: usize, message: impl ToString) -> Self {
+        Diagnostic {
+            offset,
+            message: message.to_string(),
+        }
+    }
+
+    /// The 1-based line and column, in characters, of the diagnostic in `source`
+    pub(crate) fn location(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.offset.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+}
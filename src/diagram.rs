@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::Config;
+use crate::push_str::{escape_html, push};
+
+
+/// Turns the content of a fenced code block into HTML, in place of
+/// syntax highlighting. Used to render diagrams to inline SVG.
+pub(crate) trait CodeBlockProcessor: Sync {
+    fn process(&self, code: &str) -> Result<String, String>;
+}
+
+/// Languages of diagrams that need an external command to render
+const DIAGRAM_LANGUAGES: [&str; 4] = ["mermaid", "dot", "graphviz", "plantuml"];
+
+/// The processors for each code block language.
+pub(crate) struct CodeBlockProcessors {
+    processors: HashMap<String, Box<dyn CodeBlockProcessor>>,
+    /// Whether a diagram without a command was reported, once per build
+    warned: AtomicBool,
+}
+
+impl CodeBlockProcessors {
+    /// The built-in ASCII-art renderer for `svgbob` and `bob` blocks,
+    /// plus the external commands from `Config::code_block_commands`.
+    pub(crate) fn new(config: &Config) -> Self {
+        let mut processors: HashMap<String, Box<dyn CodeBlockProcessor>> = HashMap::new();
+        processors.insert("svgbob".to_owned(), Box::new(AsciiArt));
+        processors.insert("bob".to_owned(), Box::new(AsciiArt));
+
+        for (language, command) in &config.code_block_commands {
+            processors.insert(language.clone(), Box::new(ExternalCommand(command.clone())));
+        }
+
+        CodeBlockProcessors { processors, warned: AtomicBool::new(false) }
+    }
+
+    pub(crate) fn get(&self, language: &str) -> Option<&dyn CodeBlockProcessor> {
+        self.processors.get(language).map(|processor| &**processor)
+    }
+
+    /// Whether `language` is a diagram left as code, as no command renders it.
+    /// The first one of a build prints a warning.
+    pub(crate) fn is_unrendered_diagram(&self, language: &str) -> bool {
+        if !DIAGRAM_LANGUAGES.contains(&language) || self.processors.contains_key(language) {
+            return false;
+        }
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            eprintln!("warning: {language} diagrams are shown as code, add a command to code_block_commands in config.yaml to render them");
+        }
+        true
+    }
+}
+
+/// A command reading the code on its stdin and writing SVG to its stdout,
+/// like `["dot", "-Tsvg"]`.
+struct ExternalCommand(Vec<String>);
+
+impl CodeBlockProcessor for ExternalCommand {
+    fn process(&self, code: &str) -> Result<String, String> {
+        let (program, args) = self.0.split_first().ok_or("empty code block command")?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("could not run {program}: {err}"))?;
+
+        // Written from another thread, as a command may fill its stdout before
+        // reading all of its input, and wait for its output to be read.
+        // Dropping stdin closes it so that the command sees the end of its input.
+        let mut stdin = child.stdin.take().unwrap();
+        let (written, output) = std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(code.as_bytes()));
+            let output = child.wait_with_output();
+            (writer.join().unwrap(), output)
+        });
+
+        let output = output.map_err(|err| format!("could not run {program}: {err}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{program} failed: {}", stderr.trim()));
+        }
+        written.map_err(|err| format!("could not write to {program}: {err}"))?;
+
+        let svg = String::from_utf8(output.stdout)
+            .map_err(|_| format!("{program} did not output UTF-8"))?;
+        // Drop the XML declaration and doctype, which are invalid inside HTML
+        match svg.find("<svg") {
+            Some(start) => Ok(svg[start..].trim_end().to_owned()),
+            None => Err(format!("{program} did not output an SVG")),
+        }
+    }
+}
+
+/// Renders ASCII-art diagrams, in the style of svgbob:
+/// `-` `|` `/` `\` are lines, `+` `.` `'` are corners and junctions,
+/// `<` `>` `^` `v` at the end of lines are arrows,
+/// `*` and `o` on lines are filled and hollow dots,
+/// and everything else is text.
+struct AsciiArt;
+
+/// Size of a character cell in the SVG
+const CELL_WIDTH: f32 = 8.0;
+const CELL_HEIGHT: f32 = 16.0;
+
+impl CodeBlockProcessor for AsciiArt {
+    fn process(&self, code: &str) -> Result<String, String> {
+        let grid = Grid::new(code);
+        if grid.rows.is_empty() {
+            return Err("empty diagram".to_owned());
+        }
+
+        let width = grid.width() as f32 * CELL_WIDTH;
+        let height = grid.rows.len() as f32 * CELL_HEIGHT;
+
+        let mut svg = String::new();
+        push!(
+            svg,
+            "<svg xmlns='http://www.w3.org/2000/svg' class='ascii-art' viewBox='0 0 {width} {height}' width='{width}' height='{height}'>",
+        );
+        svg.push_str(
+            "<g fill='none' stroke='currentColor' stroke-width='1.5' stroke-linecap='round'>",
+        );
+        for (y, row) in grid.rows.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                grid.draw(&mut svg, x, y, c);
+            }
+        }
+        svg.push_str("</g>");
+
+        svg.push_str("<g fill='currentColor' font-family='monospace' font-size='13.33'>");
+        for (y, row) in grid.rows.iter().enumerate() {
+            grid.write_text(&mut svg, y, row);
+        }
+        svg.push_str("</g></svg>");
+
+        Ok(svg)
+    }
+}
+
+struct Grid {
+    rows: Vec<Vec<char>>,
+}
+
+impl Grid {
+    fn new(code: &str) -> Self {
+        let mut rows: Vec<Vec<char>> = code
+            .lines()
+            .map(|line| line.trim_end().chars().collect())
+            .collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        Grid { rows }
+    }
+
+    fn width(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    fn get(&self, x: isize, y: isize) -> char {
+        if x < 0 || y < 0 {
+            return ' ';
+        }
+        self.rows
+            .get(y as usize)
+            .and_then(|row| row.get(x as usize))
+            .copied()
+            .unwrap_or(' ')
+    }
+
+    /// Whether the character at `(x, y)` is drawn rather than written as text
+    fn is_drawing(&self, x: usize, y: usize) -> bool {
+        let (x, y) = (x as isize, y as isize);
+        let c = self.get(x, y);
+        let left = self.get(x - 1, y);
+        let right = self.get(x + 1, y);
+        let in_word = left.is_alphanumeric() && right.is_alphanumeric();
+        let touches_word = left.is_alphanumeric() || right.is_alphanumeric();
+
+        match c {
+            '-' | '=' => !in_word,
+            '|' => true,
+            '/' | '\\' => {
+                let (up, down) = match c {
+                    '/' => (self.get(x + 1, y - 1), self.get(x - 1, y + 1)),
+                    _ => (self.get(x - 1, y - 1), self.get(x + 1, y + 1)),
+                };
+                up == c || down == c || is_junction(up) || is_junction(down)
+            }
+            '+' => true,
+            '.' | '\'' => self.connections(x, y) != [false; 4],
+            '>' => is_horizontal(left),
+            '<' => is_horizontal(right),
+            '^' => is_vertical(self.get(x, y + 1)),
+            'v' | 'V' => is_vertical(self.get(x, y - 1)) && !touches_word,
+            '*' | 'o' => self.connections(x, y) != [false; 4] && !touches_word,
+            _ => false,
+        }
+    }
+
+    /// Whether lines come into `(x, y)` from the left, right, top and bottom
+    fn connections(&self, x: isize, y: isize) -> [bool; 4] {
+        [
+            is_horizontal(self.get(x - 1, y)) || self.get(x - 1, y) == '<',
+            is_horizontal(self.get(x + 1, y)) || self.get(x + 1, y) == '>',
+            is_vertical(self.get(x, y - 1)) || self.get(x, y - 1) == '^',
+            is_vertical(self.get(x, y + 1)) || matches!(self.get(x, y + 1), 'v' | 'V'),
+        ]
+    }
+
+    fn draw(&self, svg: &mut String, x: usize, y: usize, c: char) {
+        if !self.is_drawing(x, y) {
+            return;
+        }
+
+        let left = x as f32 * CELL_WIDTH;
+        let top = y as f32 * CELL_HEIGHT;
+        let right = left + CELL_WIDTH;
+        let bottom = top + CELL_HEIGHT;
+        let cx = left + CELL_WIDTH / 2.0;
+        let cy = top + CELL_HEIGHT / 2.0;
+        let [from_left, from_right, from_top, from_bottom] =
+            self.connections(x as isize, y as isize);
+
+        match c {
+            '-' => line(svg, left, cy, right, cy),
+            '=' => {
+                line(svg, left, cy - 2.0, right, cy - 2.0);
+                line(svg, left, cy + 2.0, right, cy + 2.0);
+            }
+            '|' => line(svg, cx, top, cx, bottom),
+            '/' => line(svg, left, bottom, right, top),
+            '\\' => line(svg, left, top, right, bottom),
+            '+' | '.' | '\'' => {
+                let corner_radius = if c == '+' { 0.0 } else { CELL_WIDTH / 2.0 };
+                let horizontal = from_left != from_right;
+                let vertical = from_top != from_bottom;
+
+                if horizontal && vertical && corner_radius > 0.0 {
+                    // A rounded corner
+                    let (hx, vy) = (
+                        if from_left { left } else { right },
+                        if from_top { top } else { bottom },
+                    );
+                    push!(svg, "<path d='M {hx} {cy} Q {cx} {cy} {cx} {vy}'/>");
+                } else {
+                    if from_left {
+                        line(svg, left, cy, cx, cy);
+                    }
+                    if from_right {
+                        line(svg, cx, cy, right, cy);
+                    }
+                    if from_top {
+                        line(svg, cx, top, cx, cy);
+                    }
+                    if from_bottom {
+                        line(svg, cx, cy, cx, bottom);
+                    }
+                }
+            }
+            '>' => arrow(svg, [(left, cy - 4.0), (right, cy), (left, cy + 4.0)], (left, cy)),
+            '<' => arrow(svg, [(right, cy - 4.0), (left, cy), (right, cy + 4.0)], (right, cy)),
+            '^' => arrow(svg, [(cx - 4.0, bottom), (cx, top), (cx + 4.0, bottom)], (cx, bottom)),
+            'v' | 'V' => arrow(svg, [(cx - 4.0, top), (cx, bottom), (cx + 4.0, top)], (cx, top)),
+            '*' | 'o' => {
+                if from_left {
+                    line(svg, left, cy, cx - 3.0, cy);
+                }
+                if from_right {
+                    line(svg, cx + 3.0, cy, right, cy);
+                }
+                if from_top {
+                    line(svg, cx, top, cx, cy - 3.0);
+                }
+                if from_bottom {
+                    line(svg, cx, cy + 3.0, cx, bottom);
+                }
+                let fill = if c == '*' { "currentColor" } else { "none" };
+                push!(svg, "<circle cx='{cx}' cy='{cy}' r='3' fill='{fill}'/>");
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes runs of text characters of a row, merging words separated by single spaces.
+    fn write_text(&self, svg: &mut String, y: usize, row: &[char]) {
+        let mut x = 0;
+        while x < row.len() {
+            if row[x] == ' ' || self.is_drawing(x, y) {
+                x += 1;
+                continue;
+            }
+
+            let start = x;
+            let mut end = x;
+            while x < row.len() {
+                let c = row[x];
+                if c == ' ' {
+                    // A run only continues over a single space
+                    if row.get(x + 1).is_none_or(|&next| next == ' ') {
+                        break;
+                    }
+                } else if self.is_drawing(x, y) {
+                    break;
+                } else {
+                    end = x + 1;
+                }
+                x += 1;
+            }
+
+            let text: String = row[start..end].iter().collect();
+            let length = (end - start) as f32 * CELL_WIDTH;
+            push!(
+                svg,
+                "<text x='{}' y='{}' textLength='{length}' lengthAdjust='spacingAndGlyphs'>",
+                start as f32 * CELL_WIDTH,
+                y as f32 * CELL_HEIGHT + CELL_HEIGHT * 0.75,
+            );
+            escape_html(svg, &text);
+            svg.push_str("</text>");
+        }
+    }
+}
+
+fn is_horizontal(c: char) -> bool {
+    matches!(c, '-' | '=' | '+' | '.' | '\'' | '*' | 'o')
+}
+
+fn is_vertical(c: char) -> bool {
+    matches!(c, '|' | '+' | '.' | '\'' | '*' | 'o')
+}
+
+fn is_junction(c: char) -> bool {
+    matches!(c, '+' | '.' | '\'' | '*' | 'o')
+}
+
+fn line(svg: &mut String, x1: f32, y1: f32, x2: f32, y2: f32) {
+    push!(svg, "<line x1='{x1}' y1='{y1}' x2='{x2}' y2='{y2}'/>");
+}
+
+/// An arrow head with the line leading to it from `tail`
+fn arrow(svg: &mut String, head: [(f32, f32); 3], tail: (f32, f32)) {
+    let [(x1, y1), (x2, y2), (x3, y3)] = head;
+    line(svg, tail.0, tail.1, x2, y2);
+    push!(
+        svg,
+        "<polygon points='{x1},{y1} {x2},{y2} {x3},{y3}' fill='currentColor' stroke='none'/>",
+    );
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn external_commands_can_output_before_reading_all_input() {
+        // Far more than a pipe holds, in both directions
+        let code = format!("<svg>{}</svg>", "x".repeat(1 << 20));
+        let command = ExternalCommand(vec!["cat".to_owned()]);
+        assert_eq!(command.process(&code).unwrap(), code);
+    }
+
+    #[test]
+    fn external_command_arguments_are_not_split() {
+        let command = ExternalCommand(vec![
+            "sh".to_owned(),
+            "-c".to_owned(),
+            "echo '<svg>a  b</svg>'".to_owned(),
+        ]);
+        assert_eq!(command.process("").unwrap(), "<svg>a  b</svg>");
+    }
+
+    #[test]
+    fn diagrams_without_a_command_are_left_as_code() {
+        let mut config = Config::default();
+        config.code_block_commands.insert("dot".to_owned(), vec!["dot".to_owned(), "-Tsvg".to_owned()]);
+        let processors = CodeBlockProcessors::new(&config);
+
+        assert!(processors.is_unrendered_diagram("mermaid"));
+        assert!(processors.is_unrendered_diagram("mermaid"));
+        assert!(!processors.is_unrendered_diagram("dot"));
+        assert!(!processors.is_unrendered_diagram("svgbob"));
+        assert!(!processors.is_unrendered_diagram("rust"));
+    }
+}
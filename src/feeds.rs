@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+
+use crate::push_str::{escape_html, push, PushStr};
+
+
+/// A post in a feed
+pub(crate) struct Entry<'a> {
+    pub(crate) title: &'a str,
+    /// Absolute URL of the post
+    pub(crate) url: String,
+    pub(crate) date: Option<DateTime<Utc>>,
+    /// With absolute links, as feed readers have no page to resolve them from
+    pub(crate) summary_html: String,
+}
+
+/// Writes an Atom feed of `entries`, which are expected newest first.
+///
+/// Undated posts take the date of the newest post, so that the feed
+/// only changes along with its posts.
+pub(crate) fn atom(title: &str, feed_url: &str, site_url: &str, entries: &[Entry<'_>]) -> String {
+    let updated = entries.iter().filter_map(|entry| entry.date).max().unwrap_or_default();
+
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    write_element(&mut feed, "  ", "title", title);
+    write_element(&mut feed, "  ", "id", feed_url);
+    push!(feed, "  <link rel=\"self\" href=\"");
+    escape_html(&mut feed, feed_url);
+    push!(feed, "\"/>\n  <link href=\"");
+    escape_html(&mut feed, site_url);
+    push!(feed, "\"/>\n");
+    write_element(&mut feed, "  ", "updated", &updated.to_rfc3339());
+
+    for entry in entries {
+        feed.push_str("  <entry>\n");
+        write_element(&mut feed, "    ", "title", entry.title);
+        write_element(&mut feed, "    ", "id", &entry.url);
+        feed.push_str("    <link href=\"");
+        escape_html(&mut feed, &entry.url);
+        feed.push_str("\"/>\n");
+        write_element(&mut feed, "    ", "updated", &entry.date.unwrap_or(updated).to_rfc3339());
+        feed.push_str("    <summary type=\"html\">");
+        escape_html(&mut feed, &entry.summary_html);
+        feed.push_str("</summary>\n  </entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+    feed
+}
+
+fn write_element(buf: &mut impl PushStr, indent: &str, name: &str, text: &str) {
+    push!(buf, "{indent}<{name}>");
+    escape_html(buf, text);
+    push!(buf, "</{name}>\n");
+}
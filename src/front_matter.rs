@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::io;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+
+/// The YAML block between `---` lines at the very top of a post.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct FrontMatter {
+    pub(crate) title: Option<String>,
+    /// Used as the post's summary instead of its first paragraphs
+    pub(crate) description: Option<String>,
+    pub(crate) date: Option<String>,
+    /// Name of the page in URLs, instead of the file name
+    pub(crate) slug: Option<String>,
+    /// Drafts are only built with `--drafts`
+    pub(crate) draft: bool,
+    /// Date after which the post is no longer published
+    pub(crate) expires: Option<String>,
+    /// Name of the series the post is part of
+    pub(crate) series: Option<String>,
+    /// Position in the series, which is otherwise ordered by date
+    pub(crate) series_part: Option<u32>,
+    /// Name of the template in `templates/` rendering the page
+    pub(crate) template: Option<String>,
+    /// For the `_index.md` of sections, the default template of the pages
+    /// in the section and its subsections
+    pub(crate) page_template: Option<String>,
+    /// Any other field, like the terms of taxonomies
+    #[serde(flatten)]
+    pub(crate) extra: HashMap<String, serde_yaml::Value>,
+}
+
+/// Whether a post is published, and why not
+pub(crate) enum Status {
+    Published,
+    Draft,
+    /// Dated in the future
+    Scheduled(DateTime<Utc>),
+    Expired(DateTime<Utc>),
+}
+
+impl FrontMatter {
+    /// The status of the post at `now`, along with problems with its dates.
+    /// Like other problems with the front matter, invalid dates are ignored.
+    pub(crate) fn status(&self, now: DateTime<Utc>) -> (Status, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut parse = |date: Option<&str>| match date.map(parse_date) {
+            Some(Err(msg)) => {
+                problems.push(msg);
+                None
+            }
+            date => date.and_then(Result::ok),
+        };
+        let date = parse(self.date.as_deref());
+        let expires = parse(self.expires.as_deref());
+
+        let status = if self.draft {
+            Status::Draft
+        } else if let Some(date) = date.filter(|&date| date > now) {
+            Status::Scheduled(date)
+        } else if let Some(expires) = expires.filter(|&expires| expires <= now) {
+            Status::Expired(expires)
+        } else {
+            Status::Published
+        };
+        (status, problems)
+    }
+}
+
+/// Parses dates like `2023-05-01`, `2023-05-01 10:00`
+/// or `2023-05-01T10:00:00Z`, which are in UTC without a timezone.
+pub(crate) fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(date, format) {
+            return Ok(date.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| format!("invalid date {date}, expected a date like 2023-05-01"))
+}
+
+/// Splits the front matter from the markdown that follows it.
+/// Posts without front matter get the default one.
+pub(crate) fn split(source: &str) -> Result<(FrontMatter, &str), io::Error> {
+    let Some(rest) = source.strip_prefix("---") else {
+        return Ok((FrontMatter::default(), source));
+    };
+    let Some(rest) = rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")) else {
+        // A thematic break made of more than three dashes
+        return Ok((FrontMatter::default(), source));
+    };
+
+    // The closing `---` is on a line of its own, the first one when the block is empty
+    let mut end = 0;
+    let mut lines = rest.split_inclusive('\n');
+    let markdown = loop {
+        let Some(line) = lines.next() else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unterminated front matter"));
+        };
+        if line.trim_end() == "---" {
+            break &rest[end + line.len()..];
+        }
+        end += line.len();
+    };
+    let yaml = &rest[..end];
+
+    let front_matter = if yaml.trim().is_empty() {
+        FrontMatter::default()
+    } else {
+        serde_yaml::from_str(yaml)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+    };
+
+    Ok((front_matter, markdown))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_front_matter() {
+        let cases = [
+            ("---\ntitle: Hi\n---\nbody\n", Some("Hi"), "body\n"),
+            ("---\r\ntitle: Hi\r\n---\r\nbody\r\n", Some("Hi"), "body\r\n"),
+            ("---\n---\nbody\n", None, "body\n"),
+            ("---\r\n---\r\nbody", None, "body"),
+            ("---\n\n---", None, ""),
+            ("---\ntitle: Hi\n---", Some("Hi"), ""),
+            ("body\n---\n", None, "body\n---\n"),
+            ("----\nbody\n", None, "----\nbody\n"),
+        ];
+        for (source, title, markdown) in cases {
+            let (front_matter, rest) = split(source).unwrap();
+            assert_eq!(front_matter.title.as_deref(), title, "{source:?}");
+            assert_eq!(rest, markdown, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn unterminated_front_matter() {
+        for source in ["---\ntitle: Hi\n", "---\n", "---\ntitle: Hi\n----\nbody\n"] {
+            let err = split(source).err().unwrap();
+            assert_eq!(err.to_string(), "unterminated front matter", "{source:?}");
+        }
+    }
+}
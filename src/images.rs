@@ -0,0 +1,299 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::assets::StableHasher;
+use crate::push_str::{escape_href, escape_html, push, PushStr};
+
+
+/// Settings for images referenced from markdown, from `config.yaml`
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct ImageSettings {
+    /// Widths of the resized variants, only those smaller than the image are made
+    pub(crate) widths: Vec<u32>,
+    /// The `sizes` attribute, telling browsers how wide images are displayed
+    pub(crate) sizes: String,
+    /// Whether to also make WebP variants
+    pub(crate) webp: bool,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        ImageSettings {
+            widths: vec![480, 960, 1440],
+            sizes: "(max-width: 800px) 100vw, 800px".to_owned(),
+            webp: true,
+        }
+    }
+}
+
+/// A local image, along with its variants, in the output directory
+pub(crate) struct ProcessedImage {
+    /// Width and height, unknown for some images copied as they are
+    pub(crate) size: Option<(u32, u32)>,
+    /// Path of the full size image relative to the output directory
+    pub(crate) src: String,
+    /// Paths of the variants in the original format, with their width
+    pub(crate) variants: Vec<(String, u32)>,
+    /// Same for the WebP variants, full size included
+    pub(crate) webp_variants: Vec<(String, u32)>,
+}
+
+/// Copies the images posts use to `images/` in the output directory,
+/// with resized and WebP variants for responsive `srcset`s.
+///
+/// Files are named after a hash of their content, so that a build only
+/// encodes images that are new or changed, and browsers can cache them forever.
+pub(crate) struct Images<'a> {
+    output_dir: &'a Path,
+    settings: &'a ImageSettings,
+    /// Images already processed during this build, by source path
+    processed: RefCell<HashMap<PathBuf, Rc<ProcessedImage>>>,
+    /// Files of `images/` used during this build, relative to the output directory
+    used: RefCell<HashSet<String>>,
+}
+
+impl<'a> Images<'a> {
+    pub(crate) fn new(output_dir: &'a Path, settings: &'a ImageSettings) -> Self {
+        Images { output_dir, settings, processed: RefCell::default(), used: RefCell::default() }
+    }
+
+    pub(crate) fn process(&self, source: &Path) -> Result<Rc<ProcessedImage>, String> {
+        if let Some(image) = self.processed.borrow().get(source) {
+            return Ok(image.clone());
+        }
+
+        let image = Rc::new(self.process_uncached(source)?);
+        self.processed.borrow_mut().insert(source.to_owned(), image.clone());
+        Ok(image)
+    }
+
+    fn process_uncached(&self, source: &Path) -> Result<ProcessedImage, String> {
+        if !source.is_file() {
+            return Err(format!("missing image {}", source.display()));
+        }
+        let bytes = fs::read(source)
+            .map_err(|err| format!("could not read image {}: {err}", source.display()))?;
+        let format = ImageFormat::from_path(source).or_else(|_| image::guess_format(&bytes)).ok();
+
+        let mut hasher = StableHasher::default();
+        hasher.write(&bytes);
+        let stem = source.file_stem().and_then(|stem| stem.to_str()).unwrap_or("image");
+        let name = format!("images/{stem}-{:08x}", hasher.finish() as u32);
+        let extension = source.extension().and_then(|e| e.to_str()).unwrap_or("img");
+
+        let src = format!("{name}.{extension}");
+        self.write(&src, || Ok(bytes.clone()))?;
+
+        // Formats the image crate cannot decode, like SVG, are copied as they are,
+        // and so are GIFs, as resizing would lose their animation
+        let as_is = |size| ProcessedImage { size, src: src.clone(), variants: Vec::new(), webp_variants: Vec::new() };
+        let Some(format) = format.filter(|format| format.reading_enabled()) else {
+            let is_svg = extension.eq_ignore_ascii_case("svg");
+            return Ok(as_is(if is_svg { svg_size(&bytes) } else { None }));
+        };
+        if format == ImageFormat::Gif {
+            return Ok(as_is(image::image_dimensions(source).ok()));
+        }
+        let Ok(image) = image::load_from_memory_with_format(&bytes, format) else {
+            return Ok(as_is(None));
+        };
+        let (width, height) = (image.width(), image.height());
+
+        let mut variants = Vec::new();
+        let mut webp_variants = Vec::new();
+        let widths = self.settings.widths.iter().copied().filter(|&w| w < width);
+        for variant_width in widths.chain([width]) {
+            // Only resized when one of the files is missing
+            let resized = OnceCell::new();
+            let variant = || {
+                resized.get_or_init(|| {
+                    if variant_width == width {
+                        return image.clone();
+                    }
+                    let variant_height = (height as u64 * variant_width as u64 / width as u64).max(1);
+                    image.resize_exact(variant_width, variant_height as u32, FilterType::Lanczos3)
+                })
+            };
+
+            if variant_width < width {
+                let path = format!("{name}-{variant_width}w.{extension}");
+                self.write(&path, || encode(variant(), format))?;
+                variants.push((path, variant_width));
+            }
+            if self.settings.webp && format != ImageFormat::WebP {
+                let path = if variant_width < width {
+                    format!("{name}-{variant_width}w.webp")
+                } else {
+                    format!("{name}.webp")
+                };
+                self.write(&path, || encode(variant(), ImageFormat::WebP))?;
+                webp_variants.push((path, variant_width));
+            }
+        }
+        variants.push((src.clone(), width));
+
+        Ok(ProcessedImage { size: Some((width, height)), src, variants, webp_variants })
+    }
+
+    /// Writes the file at `path` in the output directory, unless a previous
+    /// build already did, as the name changes with the content
+    fn write(&self, path: &str, contents: impl FnOnce() -> Result<Vec<u8>, String>) -> Result<(), String> {
+        self.used.borrow_mut().insert(path.to_owned());
+        let path = self.output_dir.join(path);
+        if path.is_file() {
+            return Ok(());
+        }
+
+        let contents = contents()?;
+        // Written under another name first, so that an interrupted build
+        // never leaves a truncated file for the next one to trust
+        let partial = path.with_extension("partial");
+        let write = || {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&partial, contents)?;
+            fs::rename(&partial, &path)
+        };
+        write().map_err(|err| format!("could not write {}: {err}", path.display()))
+    }
+
+    /// Removes the files of `images/` that no post used during this build,
+    /// like the variants of images that changed, except those of `static_dir`.
+    pub(crate) fn remove_unused(&self, static_dir: &Path) -> Result<(), io::Error> {
+        let dir = self.output_dir.join("images");
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        let used = self.used.borrow();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(|name| format!("images/{name}")) else {
+                continue;
+            };
+            if entry.file_type()?.is_file() && !used.contains(&name) && !static_dir.join(&name).exists() {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The size an SVG asks for, from the `width` and `height` of its root
+/// element when both are in pixels, or else from its `viewBox`
+fn svg_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    static SVG_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<svg\b[^>]*>").unwrap());
+    static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"\s(width|height|viewBox)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+    });
+
+    let svg = std::str::from_utf8(bytes).ok()?;
+    let tag = SVG_TAG.find(svg)?.as_str();
+    let attribute = |name: &str| {
+        ATTRIBUTE.captures_iter(tag)
+            .find(|caps| &caps[1] == name)
+            .and_then(|caps| caps.get(2).or(caps.get(3)))
+            .map(|value| value.as_str().trim())
+    };
+    let pixels = |value: &str| value.strip_suffix("px").unwrap_or(value).parse::<f64>().ok();
+
+    if let (Some(width), Some(height)) = (attribute("width").and_then(pixels), attribute("height").and_then(pixels)) {
+        return Some((width.round() as u32, height.round() as u32));
+    }
+    let view_box: Vec<f64> = attribute("viewBox")?
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match view_box[..] {
+        [_, _, width, height] => Some((width.round() as u32, height.round() as u32)),
+        _ => None,
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    // Encoders only accept the color types their format supports
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => image.clone(),
+    };
+
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, format)
+        .map_err(|err| format!("could not encode image: {err}"))?;
+    Ok(bytes.into_inner())
+}
+
+impl ProcessedImage {
+    /// Writes the `<source>` of the WebP variants, inside a `<picture>`.
+    /// `root` is the relative link to the output directory from the page.
+    pub(crate) fn write_sources(&self, buf: &mut impl PushStr, root: &str, sizes: &str) {
+        if self.webp_variants.is_empty() {
+            return;
+        }
+        buf.push_str("<source type='image/webp' srcset='");
+        write_srcset(buf, root, &self.webp_variants);
+        buf.push_str("' sizes='");
+        escape_html(buf, sizes);
+        buf.push_str("'>");
+    }
+
+    /// Writes the attributes of the `<img>` besides `src`
+    pub(crate) fn write_attributes(&self, buf: &mut impl PushStr, root: &str, sizes: &str) {
+        if self.variants.len() > 1 {
+            buf.push_str(" srcset='");
+            write_srcset(buf, root, &self.variants);
+            buf.push_str("' sizes='");
+            escape_html(buf, sizes);
+            buf.push_str("'");
+        }
+        if let Some((width, height)) = self.size {
+            push!(buf, " width='{width}' height='{height}'");
+        }
+    }
+}
+
+fn write_srcset(buf: &mut impl PushStr, root: &str, variants: &[(String, u32)]) {
+    for (i, (path, width)) in variants.iter().enumerate() {
+        if i > 0 {
+            buf.push_str(", ");
+        }
+        escape_href(buf, root);
+        escape_href(buf, path);
+        push!(buf, " {width}w");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_sizes() {
+        let cases = [
+            (r#"<svg width="120" height="80">"#, Some((120, 80))),
+            (r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width='120px' height='80.4px'>"#, Some((120, 80))),
+            (r#"<svg width="100%" height="100%" viewBox="0 0 300 150">"#, Some((300, 150))),
+            (r#"<svg viewBox="0,0,300,150">"#, Some((300, 150))),
+            (r#"<svg width="10em" height="5em">"#, None),
+            (r#"<svg>"#, None),
+            ("not an svg", None),
+        ];
+        for (svg, size) in cases {
+            assert_eq!(svg_size(svg.as_bytes()), size, "{svg}");
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::diagnostic::Diagnostic;
+use crate::push_str::{push, PushStr};
+
+
+/// An href written by the renderer, from a link or an image
+pub(crate) struct Link {
+    /// Byte offset of the link in the source
+    pub(crate) offset: usize,
+    pub(crate) href: String,
+}
+
+/// Settings for external links, from `config.yaml`.
+///
+/// Links to other sites get the `external` class and
+/// `rel="noopener noreferrer"`, unless their domain is `undecorated`,
+/// or their title ends with `{.plain}`, like `[text](https://example.com "{.plain}")`.
+/// Adding `nofollow` and opening in a new tab are set apart.
+///
+/// Builds never use the network: external links are checked against
+/// the allowlist and a cache of URLs known to work, one per line.
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct ExternalLinks {
+    /// Whether to add the class and `rel` to external links
+    pub(crate) decorate: bool,
+    /// Domains whose links are not decorated, subdomains included
+    pub(crate) undecorated: Vec<String>,
+    /// Whether external links open in a new tab
+    pub(crate) new_tab: bool,
+    /// Domains whose links get `rel="nofollow"`, subdomains included
+    pub(crate) nofollow: Vec<String>,
+    /// Whether to report external links that are neither allowed nor cached
+    pub(crate) check: bool,
+    /// Prefixes of URLs that are always accepted, like `https://docs.rs/`
+    pub(crate) allowed: Vec<String>,
+    /// File listing URLs known to work
+    pub(crate) cache: Option<std::path::PathBuf>,
+}
+
+impl Default for ExternalLinks {
+    fn default() -> Self {
+        ExternalLinks {
+            decorate: true,
+            undecorated: Vec::new(),
+            new_tab: false,
+            nofollow: Vec::new(),
+            check: false,
+            allowed: Vec::new(),
+            cache: None,
+        }
+    }
+}
+
+impl ExternalLinks {
+    /// Writes the attributes of a link to `host`, which is on another site,
+    /// and asked not to be decorated when `plain`
+    pub(crate) fn write_attributes(&self, buf: &mut impl PushStr, host: &str, plain: bool) {
+        let mut rel = Vec::new();
+        if self.decorate && !plain && !is_in_domains(host, &self.undecorated) {
+            buf.push_str(" class='external'");
+            rel.extend(["noopener", "noreferrer"]);
+        }
+        if is_in_domains(host, &self.nofollow) {
+            rel.push("nofollow");
+        }
+
+        if !rel.is_empty() {
+            push!(buf, " rel='{}'", rel.join(" "));
+        }
+        if self.new_tab {
+            buf.push_str(" target='_blank'");
+        }
+    }
+}
+
+/// Splits the `{.plain}` marker off the end of a link title,
+/// returning the title left and whether the marker was there
+pub(crate) fn plain_marker(title: &str) -> (&str, bool) {
+    match title.trim_end().strip_suffix("{.plain}") {
+        Some(title) => (title.trim_end(), true),
+        None => (title, false),
+    }
+}
+
+/// Whether `host` is one of `domains` or one of their subdomains
+fn is_in_domains(host: &str, domains: &[String]) -> bool {
+    domains.iter().any(|domain| {
+        let domain = domain.to_ascii_lowercase();
+        host == domain || host.strip_suffix(&domain).is_some_and(|sub| sub.ends_with('.'))
+    })
+}
+
+/// The host of `href` if it points to another site than `base_url`
+pub(crate) fn external_host(href: &str, base_url: &str) -> Option<String> {
+    let link_host = host(href)?;
+    (host(base_url).as_deref() != Some(&*link_host)).then_some(link_host)
+}
+
+/// The lowercased host of an absolute `http(s)` or protocol-relative URL
+fn host(url: &str) -> Option<String> {
+    let lowercase = url.get(..8).unwrap_or(url).to_ascii_lowercase();
+    let rest = if lowercase.starts_with("https://") {
+        &url[8..]
+    } else if lowercase.starts_with("http://") {
+        &url[7..]
+    } else {
+        url.strip_prefix("//")?
+    };
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    // Keep IPv6 addresses like `[::1]:8080` whole, without their port
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// A generated page, with what links to it can point at
+pub(crate) struct Page {
+    /// Path of the page relative to the output directory, like `about.html`
+    pub(crate) url: String,
+    pub(crate) ids: HashSet<String>,
+    pub(crate) links: Vec<Link>,
+}
+
+/// Checks the links of every page, returning the index of the page
+/// and a diagnostic for each broken one.
+///
+/// Internal links must point to a generated page or to a file of the output
+/// directory, and their `#fragment` to a heading of the target page.
+pub(crate) fn check(
+    pages: &[Page],
+    output_dir: &Path,
+    external: &ExternalLinks,
+) -> Result<Vec<(usize, Diagnostic)>, io::Error> {
+    let by_url: HashMap<&str, &Page> = pages.iter().map(|page| (&*page.url, page)).collect();
+
+    let known_urls: HashSet<String> = match &external.cache {
+        Some(cache) if external.check && cache.exists() => fs::read_to_string(cache)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect(),
+        _ => HashSet::new(),
+    };
+
+    let mut broken = Vec::new();
+    for (i, page) in pages.iter().enumerate() {
+        for link in &page.links {
+            let problem = match classify(&link.href) {
+                Target::Other => None,
+                Target::External(url) => {
+                    let allowed = !external.check
+                        || known_urls.contains(url)
+                        || external.allowed.iter().any(|prefix| url.starts_with(prefix));
+                    (!allowed).then(|| format!("external link {url} is not in the allowlist nor the link cache"))
+                }
+                Target::Internal { path, fragment } => {
+                    check_internal(page, path, fragment, &by_url, output_dir)
+                }
+            };
+            if let Some(msg) = problem {
+                broken.push((i, Diagnostic::new(link.offset, msg)));
+            }
+        }
+    }
+
+    Ok(broken)
+}
+
+pub(crate) enum Target<'a> {
+    External(&'a str),
+    Internal { path: &'a str, fragment: Option<&'a str> },
+    /// Like `mailto:` links, which we cannot check
+    Other,
+}
+
+pub(crate) fn classify(href: &str) -> Target<'_> {
+    if href.starts_with("//") {
+        return Target::External(href);
+    }
+    if let Some((scheme, _)) = href.split_once(':') {
+        let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if is_scheme {
+            return match &*scheme.to_ascii_lowercase() {
+                "http" | "https" => Target::External(href),
+                _ => Target::Other,
+            };
+        }
+    }
+
+    let (href, fragment) = match href.split_once('#') {
+        Some((href, fragment)) => (href, Some(fragment)),
+        None => (href, None),
+    };
+    let path = href.split_once('?').map_or(href, |(path, _)| path);
+    Target::Internal { path, fragment }
+}
+
+fn check_internal(
+    page: &Page,
+    path: &str,
+    fragment: Option<&str>,
+    by_url: &HashMap<&str, &Page>,
+    output_dir: &Path,
+) -> Option<String> {
+    let target = if path.is_empty() {
+        page
+    } else {
+        let url = resolve(&page.url, &percent_decode(path));
+        match by_url.get(&*url) {
+            Some(target) => target,
+            None if output_dir.join(&url).is_file() => return None,
+            None => return Some(format!("link to missing page {path}")),
+        }
+    };
+
+    let fragment = percent_decode(fragment?);
+    // Empty fragments and `#top` scroll to the top of any page
+    if fragment.is_empty() || fragment == "top" || target.ids.contains(&fragment) {
+        return None;
+    }
+    Some(format!("no heading with id {fragment} in {}", target.url))
+}
+
+/// Resolves `path` relative to the page at `from`, giving a path relative
+/// to the output directory.
+/// Directories resolve to their `index.html`.
+fn resolve(from: &str, path: &str) -> String {
+    let mut segments: Vec<&str> = match path.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => {
+            let mut segments: Vec<&str> = from.split('/').collect();
+            segments.pop();
+            segments
+        }
+    };
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if path.ends_with('/') || segments.is_empty() {
+        segments.push("index.html");
+    }
+
+    segments.join("/")
+}
+
+/// Decodes `%XX` escapes, which browsers apply to URLs before matching
+/// them against files and ids.
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = s.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_markers() {
+        let cases = [
+            ("", "", false),
+            ("Docs", "Docs", false),
+            ("{.plain}", "", true),
+            ("Docs {.plain}", "Docs", true),
+            ("Docs {.plain} ", "Docs", true),
+            ("{.plain} Docs", "{.plain} Docs", false),
+        ];
+        for (title, rest, plain) in cases {
+            assert_eq!(plain_marker(title), (rest, plain), "{title:?}");
+        }
+    }
+
+    #[test]
+    fn plain_links_keep_nofollow_and_new_tab() {
+        let external = ExternalLinks {
+            nofollow: vec!["example.com".to_owned()],
+            new_tab: true,
+            ..Default::default()
+        };
+        let attributes = |plain| {
+            let mut buf = String::new();
+            external.write_attributes(&mut buf, "example.com", plain);
+            buf
+        };
+        assert_eq!(attributes(false), " class='external' rel='noopener noreferrer nofollow' target='_blank'");
+        assert_eq!(attributes(true), " rel='nofollow' target='_blank'");
+    }
+}
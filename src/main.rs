@@ -1,25 +1,79 @@
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Server, server::conn::AddrStream};
 
+pub mod assets;
+pub mod code_block;
+pub mod config;
+pub mod diagnostic;
+pub mod diagram;
+pub mod feeds;
+pub mod front_matter;
+pub mod images;
+pub mod links;
+pub mod math;
+pub mod outputs;
+pub mod paginator;
+pub mod placeholder;
 pub mod posts;
+pub mod related;
+pub mod sections;
+pub mod series;
 pub mod server;
 pub mod templater;
 pub mod push_str;
-
-
+pub mod shortcodes;
+pub mod slug;
+pub mod syntaxes;
+pub mod taxonomies;
+pub mod urls;
+
+
+/// Builds the blog from markdown, and serves it locally
+#[derive(Parser)]
+struct Cli {
+    /// Serves the site when no command is given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Builds the site to blog/
+    Build {
+        /// Also build drafts, scheduled and expired posts, with a badge
+        #[arg(long)]
+        drafts: bool,
+    },
+    /// Builds the site and serves it on http://127.0.0.1:3000
+    Serve {
+        /// Also build drafts, scheduled and expired posts, with a badge
+        #[arg(long)]
+        drafts: bool,
+    },
+}
 
 #[tokio::main]
 async fn main () -> Result<(), Box<io::Error>> {
 
-    let post_path = PathBuf::from("./markdown");
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve { drafts: false });
 
-    let posts = posts::Posts::new(post_path);
+    let config = config::Config::load(Path::new("config.yaml"))?;
+
+    let posts = posts::Posts::new(config.content_dir.clone());
 
     let get_posts = posts.fetch_posts()?;
 
-    templater::generate_html(&get_posts)?;
+    match command {
+        Command::Build { drafts } => {
+            templater::generate_html(&get_posts, &config, drafts)?;
+            return Ok(());
+        }
+        Command::Serve { drafts } => templater::generate_html(&get_posts, &config, drafts)?,
+    }
 
 
     //let content_dir = Arc::new(PathBuf::from("./blog"));
@@ -45,7 +99,7 @@ async fn main () -> Result<(), Box<io::Error>> {
 
     println!("Server listening on port http://{}", addr);
 
-    server.await.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    server.await.map_err(io::Error::other)?;
   
     Ok(())
   
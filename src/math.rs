@@ -0,0 +1,888 @@
+use crate::diagnostic::Diagnostic;
+use crate::placeholder::{self, Placeholders, SourceMap};
+use crate::push_str::{escape_html, push};
+
+
+/// Renders `$inline$` and `$$display$$` TeX math to MathML, returning the
+/// source with placeholders in its place.
+///
+/// Like pandoc, an inline opening `$` must be followed by a non-space
+/// character and the closing one preceded by a non-space character and
+/// not followed by a digit, so that prices like "$5 and $10" stay text.
+/// `\$` and dollars inside code are left untouched.
+/// Diagnostics point into `source`.
+pub(crate) fn expand(
+    source: &str,
+    options: pulldown_cmark::Options,
+    placeholders: &mut Placeholders,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (String, SourceMap) {
+    let code = placeholder::code_ranges(source, options);
+
+    let mut expanded = String::with_capacity(source.len());
+    let mut source_map = SourceMap::default();
+    let mut copied_up_to = 0;
+    let mut pos = 0;
+
+    while pos < source.len() {
+        if let Some(code) = code.iter().find(|code| code.contains(&pos)) {
+            pos = code.end;
+            continue;
+        }
+
+        let rest = &source[pos..];
+        let found = if let Some(escaped) = rest.strip_prefix('\\') {
+            // Skip the escaped character
+            pos += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+            continue;
+        } else if let Some(tex) = rest.strip_prefix("$$") {
+            match tex.find("$$") {
+                Some(end) => Some((pos + 2..pos + 2 + end, pos + 2 + end + 2, true)),
+                None => {
+                    diagnostics.push(Diagnostic::new(pos, "unterminated display math"));
+                    pos += 2;
+                    continue;
+                }
+            }
+        } else if rest.starts_with('$') {
+            inline_end(rest).map(|end| (pos + 1..pos + end, pos + end + 1, false))
+        } else {
+            None
+        };
+
+        let Some((tex, end, display)) = found else {
+            pos += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+
+        let html = match to_mathml(&source[tex.clone()], display) {
+            Ok(mathml) => mathml,
+            Err(err) => {
+                diagnostics.push(Diagnostic::new(tex.start + err.offset, &err.message));
+                let mut html = String::from("<span style='color:red'>");
+                escape_html(&mut html, &err.message);
+                html.push_str("</span>");
+                html
+            }
+        };
+
+        let span = pos..end;
+        let block = display && placeholder::is_alone_on_lines(source, &span);
+        let placeholder = placeholders.insert(html, block);
+        expanded.push_str(&source[copied_up_to..pos]);
+        source_map.record(expanded.len()..expanded.len() + placeholder.len(), span);
+        expanded.push_str(&placeholder);
+        copied_up_to = end;
+        pos = end;
+    }
+
+    expanded.push_str(&source[copied_up_to..]);
+    (expanded, source_map)
+}
+
+/// Finds the closing `$` of inline math starting at `rest[0]`.
+fn inline_end(rest: &str) -> Option<usize> {
+    if rest[1..].starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut chars = rest.char_indices().skip(1).peekable();
+    let mut previous = '$';
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            // Math never spans paragraphs
+            '\n' if rest[i + 1..].trim_start_matches([' ', '\t']).starts_with('\n') => return None,
+            '$' if i > 1 && !previous.is_whitespace() => {
+                let followed_by_digit = chars.peek().is_some_and(|&(_, next)| next.is_ascii_digit());
+                if !followed_by_digit {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+
+    None
+}
+
+/// Malformed TeX
+struct TexError {
+    /// Byte offset in the TeX source
+    offset: usize,
+    message: String,
+}
+
+type Result<T> = std::result::Result<T, TexError>;
+
+fn to_mathml(tex: &str, display: bool) -> Result<String> {
+    let mut parser = Parser { tex, pos: 0, display };
+    let row = parser.parse_row(Until::End)?;
+
+    let mut mathml = String::new();
+    push!(mathml, "<math display='{}'>", if display { "block" } else { "inline" });
+    mathml.push_str("<semantics><mrow>");
+    mathml.push_str(&row);
+    mathml.push_str("</mrow><annotation encoding='application/x-tex'>");
+    escape_html(&mut mathml, tex.trim());
+    mathml.push_str("</annotation></semantics></math>");
+    Ok(mathml)
+}
+
+/// What ends the row being parsed
+#[derive(Clone, Copy, PartialEq)]
+enum Until {
+    End,
+    Brace,
+    Right,
+    /// A cell of a `\begin{...}` environment, ended by `&`, `\\` or `\end`
+    Cell,
+}
+
+struct Parser<'a> {
+    tex: &'a str,
+    pos: usize,
+    display: bool,
+}
+
+/// A parsed element, along with whether scripts go above and below it
+/// instead of to its side.
+struct Atom {
+    mathml: String,
+    limits: bool,
+}
+
+impl Atom {
+    fn new(mathml: String) -> Self {
+        Atom { mathml, limits: false }
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.tex[self.pos..]
+    }
+
+    fn error<T>(&self, offset: usize, message: impl Into<String>) -> Result<T> {
+        Err(TexError { offset, message: message.into() })
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn parse_row(&mut self, until: Until) -> Result<String> {
+        let start = self.pos;
+        let mut row = String::new();
+
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+
+            if rest.is_empty() {
+                return match until {
+                    Until::End => Ok(row),
+                    Until::Brace => self.error(start.saturating_sub(1), "unmatched {"),
+                    Until::Right => self.error(start, "\\left without matching \\right"),
+                    Until::Cell => self.error(start, "\\begin without matching \\end"),
+                };
+            }
+            if rest.starts_with('}') {
+                if until == Until::Brace {
+                    self.pos += 1;
+                    return Ok(row);
+                }
+                return self.error(self.pos, "unmatched }");
+            }
+            if until == Until::Right && rest.starts_with("\\right") && !starts_with_letter(&rest[6..]) {
+                return Ok(row);
+            }
+            if until == Until::Cell
+                && (rest.starts_with('&') || rest.starts_with("\\\\") || rest.starts_with("\\end"))
+            {
+                return Ok(row);
+            }
+
+            let atom = self.parse_atom()?;
+            let atom = self.parse_scripts(atom)?;
+            row.push_str(&atom);
+        }
+    }
+
+    fn parse_scripts(&mut self, base: Atom) -> Result<String> {
+        let mut sub = None;
+        let mut sup = None;
+
+        loop {
+            self.skip_whitespace();
+            let offset = self.pos;
+            let slot = match self.rest().chars().next() {
+                Some('_') => &mut sub,
+                Some('^') => &mut sup,
+                Some('\'') => {
+                    // f' is f^{\prime}
+                    self.pos += 1;
+                    if sup.is_some() {
+                        return self.error(offset, "double superscript");
+                    }
+                    sup = Some("<mo>′</mo>".to_owned());
+                    continue;
+                }
+                _ => break,
+            };
+            if slot.is_some() {
+                return self.error(offset, "double subscript or superscript");
+            }
+            self.pos += 1;
+            *slot = Some(self.parse_argument("script")?);
+        }
+
+        let (under, over) = match base.limits && self.display {
+            true => ("munder", "mover"),
+            false => ("msub", "msup"),
+        };
+        let both = match base.limits && self.display {
+            true => "munderover",
+            false => "msubsup",
+        };
+
+        Ok(match (sub, sup) {
+            (None, None) => base.mathml,
+            (Some(sub), None) => format!("<{under}>{}{sub}</{under}>", base.mathml),
+            (None, Some(sup)) => format!("<{over}>{}{sup}</{over}>", base.mathml),
+            (Some(sub), Some(sup)) => format!("<{both}>{}{sub}{sup}</{both}>", base.mathml),
+        })
+    }
+
+    /// Parses the argument of a command or script:
+    /// a `{group}` or a single token.
+    fn parse_argument(&mut self, of: &str) -> Result<String> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        match self.rest().chars().next() {
+            None | Some('}') | Some('&') | Some('^') | Some('_') => {
+                self.error(offset, format!("missing argument for {of}"))
+            }
+            Some('{') => {
+                self.pos += 1;
+                Ok(format!("<mrow>{}</mrow>", self.parse_row(Until::Brace)?))
+            }
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                Ok(format!("<mn>{c}</mn>"))
+            }
+            Some(_) => Ok(self.parse_atom()?.mathml),
+        }
+    }
+
+    /// Reads a `{...}` argument verbatim, for `\text` and environment names.
+    fn parse_raw_argument(&mut self, of: &str) -> Result<&'a str> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        let Some(rest) = self.rest().strip_prefix('{') else {
+            return self.error(offset, format!("missing argument for {of}"));
+        };
+
+        let mut depth = 0;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    self.pos += 1 + i + 1;
+                    return Ok(&self.tex[offset + 1..offset + 1 + i]);
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+
+        self.error(offset, "unmatched {")
+    }
+
+    fn parse_atom(&mut self) -> Result<Atom> {
+        let offset = self.pos;
+        let c = self.rest().chars().next().unwrap();
+
+        let mathml = match c {
+            '{' => {
+                self.pos += 1;
+                format!("<mrow>{}</mrow>", self.parse_row(Until::Brace)?)
+            }
+            // A script without a base
+            '^' | '_' => "<mrow></mrow>".to_owned(),
+            '0'..='9' | '.' => {
+                let rest = self.rest();
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(rest.len());
+                let number = rest[..len].trim_end_matches('.');
+                if number.is_empty() {
+                    self.pos += 1;
+                    "<mo>.</mo>".to_owned()
+                } else {
+                    self.pos += number.len();
+                    format!("<mn>{number}</mn>")
+                }
+            }
+            '\\' => return self.parse_command(),
+            '&' => return self.error(offset, "& outside of an environment"),
+            '#' | '%' => return self.error(offset, format!("unexpected {c}")),
+            '~' => {
+                self.pos += 1;
+                "<mspace width='0.25em'/>".to_owned()
+            }
+            c if c.is_alphabetic() => {
+                self.pos += c.len_utf8();
+                format!("<mi>{c}</mi>")
+            }
+            c => {
+                self.pos += c.len_utf8();
+                let c = if c == '-' { '−' } else { c };
+                let mut mathml = String::from("<mo>");
+                escape_html(&mut mathml, c.encode_utf8(&mut [0; 4]));
+                mathml.push_str("</mo>");
+                mathml
+            }
+        };
+
+        Ok(Atom::new(mathml))
+    }
+
+    fn parse_command(&mut self) -> Result<Atom> {
+        let offset = self.pos;
+        // Skip the backslash
+        self.pos += 1;
+
+        let rest = self.rest();
+        let name_len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let name = if name_len == 0 {
+            // A single-character command, like `\{` or `\,`
+            match rest.chars().next() {
+                Some(c) => &rest[..c.len_utf8()],
+                None => return self.error(offset, "lonely \\ at end of math"),
+            }
+        } else {
+            &rest[..name_len]
+        };
+        self.pos += name.len();
+
+        if let Some(space) = spacing(name) {
+            return Ok(Atom::new(format!("<mspace width='{space}'/>")));
+        }
+        if let Some(c) = identifier(name) {
+            return Ok(Atom::new(format!("<mi>{c}</mi>")));
+        }
+        if let Some(c) = operator(name) {
+            let mut mathml = String::from("<mo>");
+            escape_html(&mut mathml, c);
+            mathml.push_str("</mo>");
+            return Ok(Atom::new(mathml));
+        }
+        if let Some((c, limits)) = large_operator(name) {
+            return Ok(Atom { mathml: format!("<mo largeop='true'>{c}</mo>"), limits });
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok(Atom::new(format!("<mi>{name}</mi><mo>&#x2061;</mo>")));
+        }
+        if LIMIT_FUNCTIONS.contains(&name) {
+            return Ok(Atom { mathml: format!("<mi>{name}</mi>"), limits: true });
+        }
+        if let Some(accent) = accent(name) {
+            let argument = self.parse_argument(&format!("\\{name}"))?;
+            let mathml = match name {
+                "underline" => format!("<munder accentunder='true'>{argument}<mo>{accent}</mo></munder>"),
+                _ => format!("<mover accent='true'>{argument}<mo>{accent}</mo></mover>"),
+            };
+            return Ok(Atom::new(mathml));
+        }
+        if let Some(variant) = math_variant(name) {
+            return self.parse_math_variant(name, variant).map(Atom::new);
+        }
+
+        let mathml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument(&format!("\\{name}"))?;
+                let denominator = self.parse_argument(&format!("\\{name}"))?;
+                format!("<mfrac>{numerator}{denominator}</mfrac>")
+            }
+            "binom" => {
+                let n = self.parse_argument("\\binom")?;
+                let k = self.parse_argument("\\binom")?;
+                format!("<mrow><mo>(</mo><mfrac linethickness='0'>{n}{k}</mfrac><mo>)</mo></mrow>")
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.rest().starts_with('[') {
+                    self.pos += 1;
+                    let index_start = self.pos;
+                    let Some(len) = self.rest().find(']') else {
+                        return self.error(index_start - 1, "unmatched [");
+                    };
+                    let index = Parser { tex: &self.tex[..index_start + len], pos: index_start, display: false }
+                        .parse_row(Until::End)?;
+                    self.pos = index_start + len + 1;
+                    let radicand = self.parse_argument("\\sqrt")?;
+                    format!("<mroot>{radicand}<mrow>{index}</mrow></mroot>")
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_argument("\\sqrt")?)
+                }
+            }
+            "text" | "textrm" | "mbox" => {
+                let text = self.parse_raw_argument(&format!("\\{name}"))?;
+                let mut mathml = String::from("<mtext>");
+                escape_html(&mut mathml, text);
+                mathml.push_str("</mtext>");
+                mathml
+            }
+            "operatorname" => {
+                let text = self.parse_raw_argument("\\operatorname")?;
+                let mut mathml = String::from("<mi>");
+                escape_html(&mut mathml, text);
+                mathml.push_str("</mi><mo>&#x2061;</mo>");
+                mathml
+            }
+            "left" => {
+                let open = self.parse_delimiter("\\left")?;
+                let inner = self.parse_row(Until::Right)?;
+                // Skip `\right`
+                self.pos += "\\right".len();
+                let close = self.parse_delimiter("\\right")?;
+                format!(
+                    "<mrow><mo fence='true' stretchy='true'>{open}</mo>{inner}<mo fence='true' stretchy='true'>{close}</mo></mrow>",
+                )
+            }
+            "right" => return self.error(offset, "\\right without matching \\left"),
+            "middle" => {
+                let delimiter = self.parse_delimiter("\\middle")?;
+                format!("<mo stretchy='true'>{delimiter}</mo>")
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" => {
+                let delimiter = self.parse_delimiter(&format!("\\{name}"))?;
+                let size = match name.trim_end_matches(['l', 'r']) {
+                    "big" => "1.2em",
+                    "Big" => "1.8em",
+                    "bigg" => "2.4em",
+                    _ => "3em",
+                };
+                format!("<mo minsize='{size}' maxsize='{size}'>{delimiter}</mo>")
+            }
+            "begin" => self.parse_environment(offset)?,
+            "end" => return self.error(offset, "\\end without matching \\begin"),
+            "\\" => return self.error(offset, "line break outside of an environment"),
+            // Style switches only affect sizes, which MathML works out itself
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => String::new(),
+            _ => return self.error(offset, format!("unknown command \\{name}")),
+        };
+
+        Ok(Atom::new(mathml))
+    }
+
+    /// Parses the delimiter following `\left`, `\right`, `\big`...
+    fn parse_delimiter(&mut self, of: &str) -> Result<String> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        let rest = self.rest();
+
+        let delimiter = match rest.chars().next() {
+            Some('.') => {
+                self.pos += 1;
+                return Ok(String::new());
+            }
+            Some('\\') => {
+                let after = &rest[1..];
+                let name_len = match after.find(|c: char| !c.is_ascii_alphabetic()) {
+                    // A single-character name, like `\{` or `\|`
+                    Some(0) => after.chars().next().map_or(0, char::len_utf8),
+                    Some(len) => len,
+                    None => after.len(),
+                };
+                if name_len == 0 {
+                    return self.error(offset, format!("missing delimiter for {of}"));
+                }
+                let name = &after[..name_len];
+                self.pos += 1 + name.len();
+                operator(name)
+            }
+            Some(c) if "()[]|/<>".contains(c) => {
+                self.pos += 1;
+                Some(match c {
+                    '<' => "⟨",
+                    '>' => "⟩",
+                    _ => &rest[..1],
+                })
+            }
+            _ => None,
+        };
+
+        match delimiter {
+            Some(delimiter) => {
+                let mut escaped = String::new();
+                escape_html(&mut escaped, delimiter);
+                Ok(escaped)
+            }
+            None => self.error(offset, format!("missing delimiter for {of}")),
+        }
+    }
+
+    fn parse_math_variant(&mut self, name: &str, variant: &str) -> Result<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let text = self.parse_raw_argument(&format!("\\{name}"));
+
+        // Plain letters can carry the variant themselves,
+        // anything else is styled as a whole
+        match text {
+            Ok(text) if !text.is_empty() && text.chars().all(char::is_alphanumeric) => {
+                Ok(format!("<mi mathvariant='{variant}'>{text}</mi>"))
+            }
+            _ => {
+                self.pos = start;
+                let argument = self.parse_argument(&format!("\\{name}"))?;
+                Ok(format!("<mstyle mathvariant='{variant}'>{argument}</mstyle>"))
+            }
+        }
+    }
+
+    fn parse_environment(&mut self, offset: usize) -> Result<String> {
+        let name = self.parse_raw_argument("\\begin")?.to_owned();
+
+        let (open, close, align) = match &*name {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left")),
+            "aligned" | "align" | "align*" | "split" | "gathered" => ("", "", Some("right left")),
+            "array" => {
+                // Column alignments aren't supported, skip them
+                self.parse_raw_argument("\\begin{array}")?;
+                ("", "", None)
+            }
+            _ => return self.error(offset, format!("unknown environment {name}")),
+        };
+
+        let mut table = String::from("<mtable");
+        if let Some(align) = align {
+            push!(table, " columnalign='{align}'");
+        }
+        table.push_str("><mtr>");
+
+        loop {
+            let cell = self.parse_row(Until::Cell)?;
+            push!(table, "<mtd>{cell}</mtd>");
+
+            let rest = self.rest();
+            if rest.starts_with('&') {
+                self.pos += 1;
+            } else if rest.starts_with("\\\\") {
+                self.pos += 2;
+                table.push_str("</mtr><mtr>");
+            } else {
+                // `\end`
+                self.pos += "\\end".len();
+                let end_offset = self.pos;
+                let end = self.parse_raw_argument("\\end")?;
+                if end != name {
+                    return self.error(end_offset, format!("\\begin{{{name}}} ended by \\end{{{end}}}"));
+                }
+                break;
+            }
+        }
+        table.push_str("</mtr></mtable>");
+
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+        Ok(format!("<mrow><mo fence='true'>{open}</mo>{table}<mo fence='true'>{close}</mo></mrow>"))
+    }
+}
+
+fn starts_with_letter(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+fn spacing(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," | "thinspace" => "0.1667em",
+        ":" | ">" | "medspace" => "0.2222em",
+        ";" | "thickspace" => "0.2778em",
+        "!" | "negthinspace" => "-0.1667em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        _ => return None,
+    })
+}
+
+fn identifier(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "emptyset" | "varnothing" => "∅",
+        "ell" => "ℓ",
+        "hbar" => "ℏ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "{" | "lbrace" => "{",
+        "}" | "rbrace" => "}",
+        "|" | "Vert" => "‖",
+        "vert" => "|",
+        "%" => "%",
+        "$" => "$",
+        "#" => "#",
+        "&" => "&",
+        "_" => "_",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "cdot" => "⋅",
+        "times" => "×",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "mid" => "∣",
+        "parallel" => "∥",
+        "perp" => "⊥",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "forall" => "∀",
+        "exists" => "∃",
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "prime" => "′",
+        "colon" => ":",
+        _ => return None,
+    })
+}
+
+/// Operators drawn larger, and whether their scripts go above and below them
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    Some(match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    })
+}
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh",
+    "tanh", "log", "ln", "lg", "exp", "deg", "dim", "ker", "arg", "hom",
+];
+
+/// Functions whose subscripts go below them in display math, like `\lim_{x \to 0}`
+const LIMIT_FUNCTIONS: &[&str] = &["lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr"];
+
+fn accent(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "hat" | "widehat" => "^",
+        "bar" | "overline" => "¯",
+        "underline" => "_",
+        "vec" => "→",
+        "tilde" | "widetilde" => "~",
+        "dot" => "˙",
+        "ddot" => "¨",
+        _ => return None,
+    })
+}
+
+fn math_variant(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "mathrm" => "normal",
+        "mathbf" | "boldsymbol" => "bold",
+        "mathit" => "italic",
+        "mathbb" => "double-struck",
+        "mathcal" => "script",
+        "mathfrak" => "fraktur",
+        "mathsf" => "sans-serif",
+        "mathtt" => "monospace",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The offset and message of the error for `tex`
+    fn error(tex: &str) -> (usize, String) {
+        match to_mathml(tex, false) {
+            Ok(mathml) => panic!("{tex:?} rendered to {mathml}"),
+            Err(err) => (err.offset, err.message),
+        }
+    }
+
+    /// The diagnostics of a whole source, with their offsets
+    fn diagnostics(source: &str) -> Vec<(usize, String)> {
+        let mut diagnostics = Vec::new();
+        expand(source, pulldown_cmark::Options::empty(), &mut Placeholders::default(), &mut diagnostics);
+        diagnostics.into_iter().map(|d| (d.offset, d.message)).collect()
+    }
+
+    #[test]
+    fn delimiters_without_a_name() {
+        assert_eq!(error("\\left\\"), (5, "missing delimiter for \\left".to_owned()));
+        assert_eq!(error("\\left( x \\right\\"), (15, "missing delimiter for \\right".to_owned()));
+        assert_eq!(error("\\big\\"), (4, "missing delimiter for \\big".to_owned()));
+    }
+
+    #[test]
+    fn delimiters_with_non_ascii_names() {
+        assert_eq!(error("\\left\\é x \\right)"), (5, "missing delimiter for \\left".to_owned()));
+        assert_eq!(error("\\left é"), (6, "missing delimiter for \\left".to_owned()));
+    }
+
+    #[test]
+    fn missing_delimiters() {
+        assert_eq!(error("\\left"), (5, "missing delimiter for \\left".to_owned()));
+        assert_eq!(error("\\left( x"), (6, "\\left without matching \\right".to_owned()));
+        assert_eq!(error("x \\right)"), (2, "\\right without matching \\left".to_owned()));
+    }
+
+    #[test]
+    fn unbalanced_braces() {
+        assert_eq!(error("x^{2"), (2, "unmatched {".to_owned()));
+        assert_eq!(error("x}"), (1, "unmatched }".to_owned()));
+        assert_eq!(error("\\text{abc"), (5, "unmatched {".to_owned()));
+    }
+
+    #[test]
+    fn missing_arguments() {
+        assert_eq!(error("\\frac{1}"), (8, "missing argument for \\frac".to_owned()));
+        assert_eq!(error("x^"), (2, "missing argument for script".to_owned()));
+        assert_eq!(error("\\sqrt"), (5, "missing argument for \\sqrt".to_owned()));
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(error("x^1^2"), (3, "double subscript or superscript".to_owned()));
+        assert_eq!(error("f'^2"), (2, "double subscript or superscript".to_owned()));
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(error("\\"), (0, "lonely \\ at end of math".to_owned()));
+        assert_eq!(error("a + \\foo"), (4, "unknown command \\foo".to_owned()));
+        assert_eq!(error("\\end{matrix}"), (0, "\\end without matching \\begin".to_owned()));
+        assert_eq!(error("a \\\\ b"), (2, "line break outside of an environment".to_owned()));
+        assert_eq!(error("a & b"), (2, "& outside of an environment".to_owned()));
+    }
+
+    #[test]
+    fn environments() {
+        assert_eq!(error("\\begin{matrix} a & b"), (18, "\\begin without matching \\end".to_owned()));
+        assert_eq!(error("\\begin"), (6, "missing argument for \\begin".to_owned()));
+    }
+
+    #[test]
+    fn errors_point_into_the_source() {
+        assert_eq!(diagnostics("Area $x \\left\\é$ here"), vec![(13, "missing delimiter for \\left".to_owned())]);
+        assert_eq!(diagnostics("$$\\left\\$$"), vec![(7, "missing delimiter for \\left".to_owned())]);
+        assert_eq!(diagnostics("a $$ b"), vec![(2, "unterminated display math".to_owned())]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::syntaxes::CACHE_DIR;
+
+
+/// The files written for each post, relative to the output directory,
+/// recorded so that a later build skipping the post removes exactly those
+#[derive(Default)]
+pub(crate) struct Outputs {
+    by_source: BTreeMap<PathBuf, Vec<String>>,
+}
+
+impl Outputs {
+    /// The outputs recorded by the previous build, none on the first one
+    pub(crate) fn load() -> Result<Self, io::Error> {
+        let contents = match fs::read_to_string(manifest_path()) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Outputs::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut outputs = Outputs::default();
+        for (source, output) in contents.lines().filter_map(|line| line.split_once('\t')) {
+            outputs.record(Path::new(source), output);
+        }
+        Ok(outputs)
+    }
+
+    /// Notes that the post at `source` wrote `output`
+    pub(crate) fn record(&mut self, source: &Path, output: impl Into<String>) {
+        self.by_source.entry(source.to_owned()).or_default().push(output.into());
+    }
+
+    /// Keeps the outputs for the next build
+    pub(crate) fn save(&self) -> Result<(), io::Error> {
+        let mut contents = String::new();
+        for (source, outputs) in &self.by_source {
+            let Some(source) = source.to_str() else { continue };
+            for output in outputs {
+                if ![source, output.as_str()].iter().any(|field| field.contains(['\t', '\n'])) {
+                    contents.push_str(&format!("{source}\t{output}\n"));
+                }
+            }
+        }
+        fs::create_dir_all(CACHE_DIR)?;
+        fs::write(manifest_path(), contents)
+    }
+
+    /// Removes the files recorded for the post at `source`, then the
+    /// folders that leaves empty, never `output_dir` itself
+    pub(crate) fn remove(&self, source: &Path, output_dir: &Path) -> Result<(), io::Error> {
+        for output in self.by_source.get(source).into_iter().flatten() {
+            // The manifest is only trusted with paths strictly inside the output directory
+            let Some(output) = inside(output) else { continue };
+            let path = output_dir.join(output);
+            if !path.is_file() {
+                continue;
+            }
+            fs::remove_file(&path)?;
+
+            let mut dir = path.parent();
+            while let Some(parent) = dir.filter(|dir| *dir != output_dir) {
+                if fs::remove_dir(parent).is_err() {
+                    break;
+                }
+                dir = parent.parent();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn manifest_path() -> PathBuf {
+    Path::new(CACHE_DIR).join("outputs")
+}
+
+/// `output` as a path below the output directory, unless it could leave it
+fn inside(output: &str) -> Option<&Path> {
+    let path = Path::new(output);
+    let mut components = path.components().peekable();
+    let below = components.peek().is_some() && components.all(|c| matches!(c, Component::Normal(_)));
+    below.then_some(path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_paths_below_the_output_directory() {
+        let cases = [
+            ("index.html", true),
+            ("notes/rust/index.html", true),
+            ("notes/./index.html", true),
+            ("", false),
+            ("..", false),
+            ("notes/../../src/main.rs", false),
+            ("/etc/passwd", false),
+        ];
+        for (output, expected) in cases {
+            assert_eq!(inside(output).is_some(), expected, "{output}");
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+use crate::urls;
+
+
+/// Splits a listing of posts into pages, the first at the canonical URL
+/// of the listing and the others at `page/2/`, `page/3/`...
+pub(crate) struct Paginator {
+    /// Output path of the listing, like `tags/rust/index.html`
+    canonical: String,
+    total_items: usize,
+    page_size: usize,
+}
+
+impl Paginator {
+    /// A `page_size` of 0 keeps every post on the first page
+    pub(crate) fn new(canonical: &str, total_items: usize, page_size: usize) -> Self {
+        let page_size = if page_size == 0 { total_items.max(1) } else { page_size };
+        Paginator { canonical: canonical.to_owned(), total_items, page_size }
+    }
+
+    /// Pages are numbered from 1, and there is always at least one
+    pub(crate) fn total_pages(&self) -> usize {
+        self.total_items.div_ceil(self.page_size).max(1)
+    }
+
+    /// Output path of page `number`
+    pub(crate) fn url(&self, number: usize) -> String {
+        if number == 1 {
+            return self.canonical.clone();
+        }
+        format!("{}page/{number}/index.html", self.dir())
+    }
+
+    /// Output path of the page redirecting `page/1/` to the canonical URL
+    pub(crate) fn redirect_url(&self) -> String {
+        format!("{}page/1/index.html", self.dir())
+    }
+
+    /// The indexes of the items on page `number`
+    pub(crate) fn items(&self, number: usize) -> Range<usize> {
+        let start = (number - 1) * self.page_size;
+        start..(start + self.page_size).min(self.total_items)
+    }
+
+    /// What templates get as `paginator`, for page `number`
+    pub(crate) fn data(&self, number: usize) -> serde_json::Value {
+        let from = self.url(number);
+        let link = |number: usize| urls::relative(&from, &self.url(number));
+        let total_pages = self.total_pages();
+
+        let pagers: Vec<_> = (1..=total_pages).map(|n| serde_json::json!({
+            "number": n,
+            "url": link(n),
+            "current": n == number,
+        })).collect();
+
+        serde_json::json!({
+            "current": number,
+            "total_pages": total_pages,
+            "total_items": self.total_items,
+            "page_size": self.page_size,
+            "first": link(1),
+            "last": link(total_pages),
+            "prev": (number > 1).then(|| link(number - 1)),
+            "next": (number < total_pages).then(|| link(number + 1)),
+            "pagers": pagers,
+        })
+    }
+
+    fn dir(&self) -> &str {
+        self.canonical.strip_suffix("index.html").unwrap_or(&self.canonical)
+    }
+}
+
+/// A page sending browsers to `to`, linked from `from`,
+/// and search engines to the absolute `canonical_url` of `to`
+pub(crate) fn redirect_html(from: &str, to: &str, canonical_url: &str) -> String {
+    let mut href = String::new();
+    crate::push_str::escape_href(&mut href, &urls::relative(from, to));
+    let mut canonical = String::new();
+    crate::push_str::escape_href(&mut canonical, canonical_url);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Redirecting…</title>\n<link rel=\"canonical\" href=\"{canonical}\">\n\
+         <meta http-equiv=\"refresh\" content=\"0; url={href}\">\n</head>\n\
+         <body><a href=\"{href}\">Click here</a> if you are not redirected.</body>\n</html>\n"
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_bounds() {
+        // Total items, page size, then where the items of each page end
+        let cases: [(usize, usize, &[usize]); 6] = [
+            (0, 10, &[0]),
+            (5, 10, &[5]),
+            (10, 10, &[10]),
+            (11, 10, &[10, 11]),
+            (7, 3, &[3, 6, 7]),
+            (7, 0, &[7]),
+        ];
+        for (total_items, page_size, ends) in cases {
+            let paginator = Paginator::new("tags/rust/index.html", total_items, page_size);
+            assert_eq!(paginator.total_pages(), ends.len(), "{total_items} by {page_size}");
+            let mut start = 0;
+            for (i, &end) in ends.iter().enumerate() {
+                assert_eq!(paginator.items(i + 1), start..end, "page {} of {total_items} by {page_size}", i + 1);
+                start = end;
+            }
+        }
+    }
+
+    #[test]
+    fn page_urls() {
+        let paginator = Paginator::new("tags/rust/index.html", 25, 10);
+        assert_eq!(paginator.url(1), "tags/rust/index.html");
+        assert_eq!(paginator.url(3), "tags/rust/page/3/index.html");
+        assert_eq!(paginator.redirect_url(), "tags/rust/page/1/index.html");
+
+        let data = paginator.data(2);
+        assert_eq!(data["first"], "../../../../tags/rust/");
+        assert_eq!(data["prev"], "../../../../tags/rust/");
+        assert_eq!(data["next"], "../../../../tags/rust/page/3/");
+        assert_eq!(data["last"], "../../../../tags/rust/page/3/");
+
+        let paginator = Paginator::new("index.html", 25, 10);
+        assert_eq!(paginator.url(2), "page/2/index.html");
+        assert_eq!(paginator.data(1)["first"], "./");
+        assert_eq!(paginator.data(1)["prev"], serde_json::Value::Null);
+    }
+}
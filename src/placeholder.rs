@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+
+/// HTML that is produced before the markdown is parsed, like shortcodes
+/// and math, is swapped for HTML comments in the source so that
+/// pulldown-cmark leaves it alone, then swapped back while rendering.
+#[derive(Default)]
+pub(crate) struct Placeholders {
+    rendered: Vec<String>,
+}
+
+impl Placeholders {
+    /// Stores `html` and returns what to put in the source instead.
+    ///
+    /// Block placeholders are HTML comments, which pulldown-cmark keeps out
+    /// of paragraphs. Inline ones are empty tags, because a line starting
+    /// with a comment would end the paragraph it is in.
+    pub(crate) fn insert(&mut self, html: String, block: bool) -> String {
+        let placeholder = match block {
+            true => format!("<!--placeholder:{}-->", self.rendered.len()),
+            false => format!("<placeholder n='{}'/>", self.rendered.len()),
+        };
+        self.rendered.push(html);
+        placeholder
+    }
+
+    /// Replaces the placeholders found in a piece of HTML.
+    pub(crate) fn substitute(&self, html: &str) -> String {
+        PLACEHOLDER
+            .replace_all(html, |captures: &regex::Captures<'_>| {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(2))
+                    .and_then(|n| n.as_str().parse::<usize>().ok())
+                    .and_then(|i| self.rendered.get(i))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .into_owned()
+    }
+}
+
+/// Maps offsets in a source where some spans were replaced by placeholders
+/// back to offsets in the original source, for diagnostics.
+#[derive(Default)]
+pub(crate) struct SourceMap {
+    /// Spans in the expanded source, with the spans they replaced
+    replacements: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl SourceMap {
+    pub(crate) fn record(&mut self, expanded: Range<usize>, original: Range<usize>) {
+        self.replacements.push((expanded, original));
+    }
+
+    pub(crate) fn original_offset(&self, offset: usize) -> usize {
+        let replaced_before = self.replacements.partition_point(|(expanded, _)| expanded.start <= offset);
+        match replaced_before.checked_sub(1).map(|i| &self.replacements[i]) {
+            None => offset,
+            Some((expanded, original)) if expanded.contains(&offset) => original.start,
+            Some((expanded, original)) => offset - expanded.end + original.end,
+        }
+    }
+}
+
+/// Whether `range` is the only thing on the lines it spans,
+/// and can thus be replaced by a block placeholder.
+pub(crate) fn is_alone_on_lines(source: &str, range: &Range<usize>) -> bool {
+    let line_start = source[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[range.end..].find('\n').map_or(source.len(), |i| range.end + i);
+
+    source[line_start..range.start].trim().is_empty()
+        && source[range.end..line_end].trim().is_empty()
+}
+
+/// Byte ranges of code blocks and code spans, where no placeholder
+/// should ever be inserted so that posts can show the raw syntax.
+pub(crate) fn code_ranges(source: &str, options: pulldown_cmark::Options) -> Vec<Range<usize>> {
+    pulldown_cmark::Parser::new_ext(source, options)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(_))
+            | pulldown_cmark::Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<!--placeholder:(\d+)-->|<placeholder n='(\d+)'/>").unwrap()
+});
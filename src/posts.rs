@@ -1,6 +1,6 @@
 use std::io::{ Error, Read };
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 
 pub struct Posts {
@@ -14,27 +14,66 @@ impl Posts {
         }
     }
 
+    /// Reads every `.md` file of the content directory and its subdirectories,
+    /// sorted by path so that builds do not depend on the order of directory entries.
     pub fn fetch_posts(&self) -> Result<Vec<(PathBuf, String)>, Error>{
 
         let mut posts = vec![];
+        fetch_dir(&self.post_path, &mut posts)?;
+        posts.sort_by(|(a, _), (b, _)| a.cmp(b));
 
-        let entries = fs::read_dir(&self.post_path)?;
-            //iterate over the contents of the directory
-            for entry in entries {
-                let entry = entry?;
-                let path = entry.path();
+        Ok(posts)
 
-                //check if the entry is a file and has .md extension
-                if entry.file_type()?.is_file() && path.extension()
-                    .and_then(|e| e.to_str()) == Some("md") {
-                        let mut file = File::open(&path)?;
-                        let mut contents = String::new();
-                        file.read_to_string(&mut contents)?;
-                        posts.push((path, contents));
-                    }
-            }
+    }
+}
+
+fn fetch_dir(dir: &Path, posts: &mut Vec<(PathBuf, String)>) -> Result<(), Error> {
+    let entries = fs::read_dir(dir)?;
+        //iterate over the contents of the directory
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+
+            //check if the entry is a file and has .md extension
+            if entry.file_type()?.is_file() && path.extension()
+                .and_then(|e| e.to_str()) == Some("md") {
+                    let mut file = File::open(&path)?;
+                    let mut contents = String::new();
+                    file.read_to_string(&mut contents)?;
+                    posts.push((path, contents));
+                } else if entry.file_type()?.is_dir() && path.join("index.md").is_file() {
+                    // a bundle, the post along with its images and other files
+                    warn_markdown_in_bundle(&path, &path)?;
+                    let path = path.join("index.md");
+                    let contents = fs::read_to_string(&path)?;
+                    posts.push((path, contents));
+                } else if entry.file_type()?.is_dir() {
+                    // a section, with posts of its own
+                    fetch_dir(&path, posts)?;
+                }
+        }
 
-            Ok(posts)
+        Ok(())
+}
+
+
+/// Bundles are a single post, so any other `.md` file in `dir`,
+/// including in its subfolders, is never rendered.
+fn warn_markdown_in_bundle(bundle: &Path, dir: &Path) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
 
+        if entry.file_type()?.is_dir() {
+            warn_markdown_in_bundle(bundle, &path)?;
+        } else if path.extension().and_then(|e| e.to_str()) == Some("md") && path != bundle.join("index.md") {
+            eprintln!(
+                "warning: {}: not rendered, as {} is a bundle",
+                path.display(),
+                bundle.display(),
+            );
+        }
     }
+
+    Ok(())
 }
\ No newline at end of file
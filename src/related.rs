@@ -0,0 +1,96 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+
+/// What a post is compared with others on
+pub(crate) struct Features<'a> {
+    pub(crate) date: Option<DateTime<Utc>>,
+    /// Terms of every taxonomy, like `tags/rust`
+    pub(crate) terms: HashSet<String>,
+    pub(crate) summary: &'a str,
+    /// Whether the post takes part at all, which the home page and sections do not
+    pub(crate) is_post: bool,
+}
+
+/// The older and newer posts of each post, by post index.
+/// Undated posts have neither, and are skipped.
+pub(crate) fn chronological(posts: &[Features<'_>]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut dated: Vec<usize> = (0..posts.len())
+        .filter(|&i| posts[i].is_post && posts[i].date.is_some())
+        .collect();
+    dated.sort_by_key(|&i| (posts[i].date, i));
+
+    let mut neighbours = vec![(None, None); posts.len()];
+    for (n, &i) in dated.iter().enumerate() {
+        let older = n.checked_sub(1).map(|n| dated[n]);
+        let newer = dated.get(n + 1).copied();
+        neighbours[i] = (older, newer);
+    }
+    neighbours
+}
+
+/// Up to `count` posts related to each post, by post index, best first.
+///
+/// Each shared term counts for 1, and the similarity of the words
+/// of summaries for up to 1 more, so that terms come first.
+pub(crate) fn related(posts: &[Features<'_>], count: usize) -> Vec<Vec<usize>> {
+    let words: Vec<WordCounts> = posts.iter().map(|post| WordCounts::new(post.summary)).collect();
+
+    (0..posts.len()).map(|i| {
+        if !posts[i].is_post || count == 0 {
+            return Vec::new();
+        }
+
+        let mut scores: Vec<(f64, usize)> = (0..posts.len())
+            .filter(|&j| j != i && posts[j].is_post)
+            .map(|j| {
+                let shared = posts[i].terms.intersection(&posts[j].terms).count();
+                (shared as f64 + words[i].similarity(&words[j]), j)
+            })
+            .filter(|&(score, _)| score > 0.0)
+            .collect();
+        // Newer posts win ties
+        scores.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| posts[b.1].date.cmp(&posts[a.1].date)));
+
+        scores.into_iter().take(count).map(|(_, j)| j).collect()
+    }).collect()
+}
+
+/// Words too common to tell posts apart
+const STOP_WORDS: &[&str] = &[
+    "about", "also", "and", "are", "but", "can", "for", "from", "had", "has", "have", "how", "into",
+    "its", "just", "more", "not", "one", "our", "out", "than", "that", "the", "their", "them", "then",
+    "there", "these", "they", "this", "was", "what", "when", "which", "who", "will", "with", "you", "your",
+];
+
+struct WordCounts {
+    counts: HashMap<String, f64>,
+    norm: f64,
+}
+
+impl WordCounts {
+    fn new(text: &str) -> Self {
+        let mut counts = HashMap::new();
+        let words = text
+            .split(|c: char| !c.is_alphanumeric())
+            .map(str::to_lowercase)
+            .filter(|word| word.chars().count() > 2 && !STOP_WORDS.contains(&&**word));
+        for word in words {
+            *counts.entry(word).or_insert(0.0) += 1.0;
+        }
+        let norm = counts.values().map(|count| count * count).sum::<f64>().sqrt();
+        WordCounts { counts, norm }
+    }
+
+    /// Cosine similarity, from 0 for no common words to 1 for the same words
+    fn similarity(&self, other: &WordCounts) -> f64 {
+        if self.norm == 0.0 || other.norm == 0.0 {
+            return 0.0;
+        }
+        let dot: f64 = self.counts.iter()
+            .filter_map(|(word, count)| Some(count * other.counts.get(word)?))
+            .sum();
+        dot / (self.norm * other.norm)
+    }
+}
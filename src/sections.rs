@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::front_matter::FrontMatter;
+use crate::urls;
+
+
+/// A folder of the content directory, along with what it holds.
+///
+/// Its `_index.md`, if any, is rendered as the section page,
+/// with the `section.hbs` template listing its pages and subsections.
+/// The root section's page is the home page, from `index.md` or `_index.md`.
+pub(crate) struct Section {
+    /// Path relative to the content directory, empty for the root
+    pub(crate) dir: PathBuf,
+    /// Index of the `_index.md` among the posts
+    pub(crate) index: Option<usize>,
+    /// Indexes of the posts directly in the folder, bundles included
+    pub(crate) pages: Vec<usize>,
+    /// Folders of the subsections, sorted
+    pub(crate) subsections: Vec<PathBuf>,
+}
+
+pub(crate) struct Sections {
+    by_dir: BTreeMap<PathBuf, Section>,
+}
+
+impl Sections {
+    /// `posts` are source paths relative to the content directory
+    pub(crate) fn new<'a>(posts: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut by_dir: BTreeMap<PathBuf, Section> = BTreeMap::new();
+        let mut section = |dir: &Path| {
+            by_dir.entry(dir.to_owned()).or_insert_with(|| Section {
+                dir: dir.to_owned(),
+                index: None,
+                pages: Vec::new(),
+                subsections: Vec::new(),
+            });
+        };
+        let posts: Vec<&Path> = posts.into_iter().collect();
+
+        // Every ancestor of a post is a section, even without an `_index.md`
+        for post in &posts {
+            for dir in section_dir(post).ancestors() {
+                section(dir);
+            }
+        }
+
+        for (i, post) in posts.iter().enumerate() {
+            let section = by_dir.get_mut(section_dir(post)).unwrap();
+            if is_section_index(post) {
+                section.index = Some(i);
+            } else {
+                section.pages.push(i);
+            }
+        }
+
+        let dirs: Vec<PathBuf> = by_dir.keys().cloned().collect();
+        for dir in dirs {
+            if let Some(parent) = dir.parent() {
+                by_dir.get_mut(parent).unwrap().subsections.push(dir);
+            }
+        }
+
+        Sections { by_dir }
+    }
+
+    /// The section whose page is rendered from the post at `index`
+    pub(crate) fn of_index(&self, index: usize) -> Option<&Section> {
+        self.by_dir.values().find(|section| section.index == Some(index))
+    }
+
+    pub(crate) fn get(&self, dir: &Path) -> Option<&Section> {
+        self.by_dir.get(dir)
+    }
+
+    /// The `page_template` of the closest section of the post at `source`
+    /// which sets one, given the front matter of every post
+    pub(crate) fn page_template<'a>(&self, source: &Path, front_matters: &[&'a FrontMatter]) -> Option<&'a str> {
+        section_dir(source).ancestors().find_map(|dir| {
+            let index = self.by_dir.get(dir)?.index?;
+            front_matters[index].page_template.as_deref()
+        })
+    }
+}
+
+/// Whether `source` is the `_index.md` of a section, or the root `index.md`,
+/// which makes the home page list the posts at the root
+pub(crate) fn is_section_index(source: &Path) -> bool {
+    source.file_name().is_some_and(|name| name == "_index.md") || source == Path::new("index.md")
+}
+
+/// The folder of the section a post belongs to, which for bundles
+/// is the folder above their own
+fn section_dir(source: &Path) -> &Path {
+    let dir = urls::bundle_dir(source).unwrap_or(source);
+    dir.parent().unwrap_or(Path::new(""))
+}
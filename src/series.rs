@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use crate::front_matter::{self, FrontMatter};
+use crate::slug::slugify;
+
+
+/// Posts meant to be read in order, like the parts of a tutorial,
+/// sharing the `series` field of their front matter.
+pub(crate) struct Series {
+    /// As first written in a post
+    pub(crate) name: String,
+    pub(crate) slug: String,
+    /// Indexes of the posts, in reading order
+    pub(crate) posts: Vec<usize>,
+}
+
+impl Series {
+    /// Output path of the landing page listing the posts, like `series/rust-apis/index.html`
+    pub(crate) fn url(&self) -> String {
+        format!("series/{}/index.html", self.slug)
+    }
+
+    /// Where the post at `index` is in the series, from 0
+    pub(crate) fn position(&self, index: usize) -> Option<usize> {
+        self.posts.iter().position(|&post| post == index)
+    }
+}
+
+/// Groups posts into series, ordered by their `series_part`, then by date.
+/// Returns the series, and problems with the front matter of posts by post index.
+pub(crate) fn collect(posts: &[&FrontMatter]) -> (Vec<Series>, Vec<(usize, String)>) {
+    let mut by_slug: BTreeMap<String, Series> = BTreeMap::new();
+    let mut problems = Vec::new();
+
+    for (i, front_matter) in posts.iter().enumerate() {
+        let Some(name) = &front_matter.series else {
+            if front_matter.series_part.is_some() {
+                problems.push((i, "series_part is set but not series".to_owned()));
+            }
+            continue;
+        };
+
+        let slug = slugify(name);
+        if slug.is_empty() {
+            problems.push((i, format!("series {name:?} has no letters or digits to name its page after")));
+            continue;
+        }
+        let series = by_slug.entry(slug.clone()).or_insert_with(|| Series {
+            name: name.trim().to_owned(),
+            slug: slug.clone(),
+            posts: Vec::new(),
+        });
+        if series.name.to_lowercase() != name.trim().to_lowercase() {
+            problems.push((i, format!("series {name:?} shares the page series/{slug}/ with {:?}", series.name)));
+        }
+        series.posts.push(i);
+    }
+
+    for series in by_slug.values_mut() {
+        let key = |i: usize| {
+            let date = posts[i].date.as_deref().and_then(|date| front_matter::parse_date(date).ok());
+            // Posts without a part come after the numbered ones
+            (posts[i].series_part.is_none(), posts[i].series_part, date.is_none(), date, i)
+        };
+        series.posts.sort_by_key(|&i| key(i));
+
+        for pair in series.posts.windows(2) {
+            if let Some(part) = posts[pair[1]].series_part.filter(|&part| posts[pair[0]].series_part == Some(part)) {
+                problems.push((pair[1], format!("series_part {part} is used twice in {:?}", series.name)));
+            }
+        }
+    }
+
+    (by_slug.into_values().collect(), problems)
+}
@@ -17,6 +17,19 @@ pub(crate) async fn serve_html(
         content_dir.join(request_path)
     };
 
+    // Pretty URLs, listings and bundles are folders with an index.html
+    let index = target_path.join("index.html");
+    if target_path.is_dir() && index.is_file() && !req.uri().path().ends_with('/') {
+        // Pages link to each other relatively, which only works from the folder itself
+        let response = Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header("Location", format!("{}/", req.uri().path()))
+            .body(Body::empty())
+            .unwrap();
+        return Ok(response);
+    }
+    let target_path = if index.is_file() { index } else { target_path };
+
     println!("Target Path is: {:?}", target_path);
 
 
@@ -30,7 +43,7 @@ pub(crate) async fn serve_html(
                     .status(StatusCode::OK)
                     .body(Body::from(contents))
                     .unwrap();
-                return Ok(response);
+                Ok(response)
             }
             Err(err) => {
                 eprintln!("Failed to read file: {}", err);
@@ -38,7 +51,7 @@ pub(crate) async fn serve_html(
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
                     .body(Body::empty())
                     .unwrap();
-                return Ok(error_response);
+                Ok(error_response)
             }
         }
 
@@ -61,7 +74,7 @@ pub(crate) async fn serve_html(
             .unwrap();
 
 
-        return Ok(response);
+        Ok(response)
 
     } else {
         println!("File not found");
@@ -0,0 +1,289 @@
+use std::io;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::diagnostic::Diagnostic;
+use crate::placeholder::{self, Placeholders, SourceMap};
+use crate::push_str::escape_html;
+
+
+/// Handlebars templates that can be called from markdown, either inline
+/// `{{< youtube id="..." >}}`, or as a block wrapping some markdown:
+///
+/// ```text
+/// {{< callout kind="warning" >}}
+/// Some *markdown* content
+/// {{< /callout >}}
+/// ```
+///
+/// Arguments are available to the template by name, and the rendered
+/// inner markdown of the block form as `body`.
+/// Templates are rendered in strict mode, so an argument that is used
+/// outside of an `{{#if}}` is required.
+pub(crate) struct Shortcodes {
+    registry: handlebars::Handlebars<'static>,
+}
+
+impl Shortcodes {
+    /// Registers every `.hbs` file in `dir` as a shortcode named after the file.
+    pub(crate) fn load(dir: &Path) -> Result<Self, io::Error> {
+        let mut registry = handlebars::Handlebars::new();
+        registry.set_strict_mode(true);
+
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("hbs") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                registry.register_template_file(name, &path)
+                    .map_err(io::Error::other)?;
+            }
+        }
+
+        Ok(Shortcodes { registry })
+    }
+
+    /// Renders every shortcode in `source`, returning the source with
+    /// placeholders in their place.
+    /// Diagnostics point into `source`, and the inner markdown of block
+    /// shortcodes is rendered by `render_body`, given its offset in `source`.
+    pub(crate) fn expand(
+        &self,
+        source: &str,
+        options: pulldown_cmark::Options,
+        placeholders: &mut Placeholders,
+        diagnostics: &mut Vec<Diagnostic>,
+        mut render_body: impl FnMut(usize, &str) -> String,
+    ) -> (String, SourceMap) {
+        let tags = find_tags(source, options);
+
+        let mut expanded = String::with_capacity(source.len());
+        let mut source_map = SourceMap::default();
+        let mut copied_up_to = 0;
+        let mut i = 0;
+
+        while i < tags.len() {
+            let tag = &tags[i];
+            expanded.push_str(&source[copied_up_to..tag.span.start]);
+
+            let rendered = match &tag.kind {
+                Err(msg) => Err(msg.clone()),
+                Ok(TagKind::Close) => Err(format!("closing shortcode {} was never opened", tag.name)),
+                Ok(TagKind::Open(args)) => match find_closing(&tags, i) {
+                    Some(close) => {
+                        let inner = &source[tag.span.end..tags[close].span.start];
+                        let body = render_body(tag.span.end, inner);
+
+                        i = close;
+                        self.render(&tag.name, args, Some(body))
+                    }
+                    None => self.render(&tag.name, args, None),
+                },
+            };
+            let end = tags[i].span.end;
+
+            let html = rendered.unwrap_or_else(|msg| {
+                diagnostics.push(Diagnostic::new(tag.span.start, &msg));
+                error(&msg)
+            });
+
+            let span = tag.span.start..end;
+            let placeholder = placeholders.insert(html, placeholder::is_alone_on_lines(source, &span));
+            source_map.record(expanded.len()..expanded.len() + placeholder.len(), span);
+            expanded.push_str(&placeholder);
+            copied_up_to = end;
+            i += 1;
+        }
+
+        expanded.push_str(&source[copied_up_to..]);
+        (expanded, source_map)
+    }
+
+    fn render(
+        &self,
+        name: &str,
+        args: &Map<String, Value>,
+        body: Option<String>,
+    ) -> Result<String, String> {
+        if !self.registry.has_template(name) {
+            return Err(format!("unknown shortcode {name}"));
+        }
+
+        let mut data = args.clone();
+        if let Some(body) = body {
+            data.insert("body".to_owned(), Value::String(body));
+        }
+
+        self.registry
+            .render(name, &data)
+            .map_err(|err| format!("shortcode {name}: {}", err.desc))
+    }
+}
+
+struct Tag {
+    /// The whole `{{< ... >}}`
+    span: Range<usize>,
+    name: String,
+    kind: Result<TagKind, String>,
+}
+
+enum TagKind {
+    Open(Map<String, Value>),
+    Close,
+}
+
+/// Finds all shortcode tags, skipping those inside code so that
+/// posts can still show shortcodes verbatim.
+fn find_tags(source: &str, options: pulldown_cmark::Options) -> Vec<Tag> {
+    let code = placeholder::code_ranges(source, options);
+
+    let mut tags = Vec::new();
+    let mut pos = 0;
+
+    while let Some(start) = source[pos..].find("{{<").map(|i| i + pos) {
+        let Some(end) = source[start..].find(">}}").map(|i| i + start + ">}}".len()) else {
+            break;
+        };
+
+        if let Some(code) = code.iter().find(|code| code.contains(&start)) {
+            pos = code.end.max(start + 1);
+            continue;
+        }
+
+        let inner = source[start + "{{<".len()..end - ">}}".len()].trim();
+        tags.push(match inner.strip_prefix('/') {
+            Some(name) => Tag {
+                span: start..end,
+                name: name.trim().to_owned(),
+                kind: Ok(TagKind::Close),
+            },
+            None => {
+                let (name, args) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+                Tag {
+                    span: start..end,
+                    name: name.to_owned(),
+                    kind: parse_args(args).map(TagKind::Open),
+                }
+            }
+        });
+        pos = end;
+    }
+
+    tags
+}
+
+/// Finds the tag closing the one at `open`, accounting for nested
+/// shortcodes of the same name.
+fn find_closing(tags: &[Tag], open: usize) -> Option<usize> {
+    let name = &tags[open].name;
+    let mut depth = 0;
+
+    for (i, tag) in tags.iter().enumerate().skip(open + 1) {
+        if &tag.name != name {
+            continue;
+        }
+        match tag.kind {
+            Ok(TagKind::Open(_)) => depth += 1,
+            Ok(TagKind::Close) if depth == 0 => return Some(i),
+            Ok(TagKind::Close) => depth -= 1,
+            Err(_) => {}
+        }
+    }
+
+    None
+}
+
+/// Parses `key="value" other=value` arguments.
+fn parse_args(mut args: &str) -> Result<Map<String, Value>, String> {
+    let mut map = Map::new();
+
+    loop {
+        args = args.trim_start();
+        if args.is_empty() {
+            return Ok(map);
+        }
+
+        let Some((key, rest)) = args.split_once('=') else {
+            return Err(format!("shortcode argument {args} must be of the form key=\"value\""));
+        };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("invalid shortcode argument name {key}"));
+        }
+
+        let rest = rest.trim_start();
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => {
+                        if let Some((_, c)) = chars.next() {
+                            value.push(c);
+                        }
+                    }
+                    Some((i, '"')) => {
+                        args = &quoted[i + 1..];
+                        break;
+                    }
+                    Some((_, c)) => value.push(c),
+                    None => return Err(format!("unterminated value for shortcode argument {key}")),
+                }
+            }
+            value
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            args = &rest[end..];
+            rest[..end].to_owned()
+        };
+
+        map.insert(key.to_owned(), Value::String(value));
+    }
+}
+
+fn error(msg: &str) -> String {
+    let mut html = String::from("<span style='color:red'>");
+    escape_html(&mut html, msg);
+    html.push_str("</span>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> Map<String, Value> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn figure_caption_is_optional() {
+        let shortcodes = Shortcodes::load(Path::new("templates/shortcodes")).unwrap();
+
+        let html = shortcodes.render("figure", &args(&[("src", "cat.png")]), None).unwrap();
+        assert!(!html.contains("figcaption"));
+
+        let html = shortcodes
+            .render("figure", &args(&[("src", "cat.png"), ("caption", "A cat")]), None)
+            .unwrap();
+        assert!(html.contains("alt=\"A cat\""));
+        assert!(html.contains("<figcaption>A cat</figcaption>"));
+    }
+
+    #[test]
+    fn malformed_arguments_are_rejected() {
+        assert!(parse_args("src").is_err());
+        assert!(parse_args("caption=\"unterminated").is_err());
+        assert_eq!(parse_args("a=\"x \\\" y\" b=z").unwrap(), args(&[("a", "x \" y"), ("b", "z")]));
+    }
+}
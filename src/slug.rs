@@ -0,0 +1,85 @@
+use std::collections::HashSet;
+
+/// Turns heading text into an anchor the same way GitHub does:
+/// lowercase everything, drop punctuation and symbols (emoji included),
+/// and replace each space with a `-`.
+/// Letters and digits from any script are kept as they are.
+pub(crate) fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            slug.push(c);
+        } else if c == ' ' {
+            slug.push('-');
+        }
+    }
+
+    slug
+}
+
+/// Keeps track of the slugs handed out within a single page,
+/// so that repeated headings get `-1`, `-2`... suffixes like on GitHub.
+pub(crate) struct Slugger {
+    used: HashSet<String>,
+}
+
+impl Slugger {
+    /// `reserved` are ids which are already taken, e.g. explicit `{#id}`s,
+    /// and that generated slugs must never collide with.
+    pub(crate) fn new(reserved: HashSet<String>) -> Self {
+        Slugger { used: reserved }
+    }
+
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+
+        let mut slug = base.clone();
+        let mut suffix = 0;
+        while slug.is_empty() || self.used.contains(&slug) {
+            suffix += 1;
+            slug = if base.is_empty() {
+                format!("section-{suffix}")
+            } else {
+                format!("{base}-{suffix}")
+            };
+        }
+
+        self.used.insert(slug.clone());
+        slug
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        let cases = [
+            ("Hello World", "hello-world"),
+            ("  Trimmed  ", "trimmed"),
+            ("What's new?", "whats-new"),
+            ("snake_case and kebab-case", "snake_case-and-kebab-case"),
+            ("Two  spaces", "two--spaces"),
+            ("Rust 🦀 crabs", "rust--crabs"),
+            ("Ünïcödé Straße", "ünïcödé-straße"),
+            ("日本語", "日本語"),
+            ("!!!", ""),
+        ];
+        for (text, slug) in cases {
+            assert_eq!(slugify(text), slug, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn repeated_slugs() {
+        let mut slugger = Slugger::new(HashSet::from(["intro".to_owned()]));
+        assert_eq!(slugger.slug("Intro"), "intro-1");
+        assert_eq!(slugger.slug("Usage"), "usage");
+        assert_eq!(slugger.slug("Usage"), "usage-1");
+        assert_eq!(slugger.slug("?"), "section-1");
+        assert_eq!(slugger.slug("!"), "section-2");
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use syntect::parsing::SyntaxSet;
+
+use crate::assets::StableHasher;
+use crate::config::Config;
+
+
+/// Where builds keep what the next one reuses, like compiled syntax sets
+pub(crate) const CACHE_DIR: &str = ".cache";
+
+/// Loads syntect's default syntaxes, plus the `.sublime-syntax` files
+/// in `Config::syntaxes_dir`.
+///
+/// Compiling syntaxes takes a while, so the merged set is dumped
+/// to `.cache/` and reused as long as the syntax files and the version
+/// of syntect, which the format of dumps depends on, do not change.
+pub(crate) fn load(config: &Config) -> Result<SyntaxSet, io::Error> {
+    let files = syntax_files(&config.syntaxes_dir)?;
+    if files.is_empty() {
+        return Ok(SyntaxSet::load_defaults_newlines());
+    }
+
+    let mut hasher = StableHasher::default();
+    hasher.write(env!("SYNTECT_VERSION").as_bytes());
+    for file in &files {
+        let contents = fs::read(file)?;
+        // Lengths first, so that moving bytes from one field to the next changes the key
+        for field in [file.to_string_lossy().as_bytes(), &contents] {
+            hasher.write(&(field.len() as u64).to_le_bytes());
+            hasher.write(field);
+        }
+    }
+    let cache_path = Path::new(CACHE_DIR).join(format!("syntaxes-{:016x}.packdump", hasher.finish()));
+
+    if let Ok(syntax_set) = syntect::dumps::from_uncompressed_dump_file(&cache_path) {
+        return Ok(syntax_set);
+    }
+
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    builder.add_from_folder(&config.syntaxes_dir, true).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {err}", config.syntaxes_dir.display()),
+        )
+    })?;
+    let syntax_set = builder.build();
+
+    // The cache is only an optimization, failing to write it is not an error
+    if let Err(err) = write_cache(&cache_path, &syntax_set) {
+        eprintln!("warning: could not cache syntaxes in {}: {err}", cache_path.display());
+    }
+
+    Ok(syntax_set)
+}
+
+/// The `.sublime-syntax` files in `dir` and its subfolders, sorted so that
+/// the cache key does not depend on the order of directory entries.
+fn syntax_files(dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().and_then(|e| e.to_str()) == Some("sublime-syntax") {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Replaces the cached dumps with the one of `syntax_set`
+fn write_cache(path: &Path, syntax_set: &SyntaxSet) -> Result<(), io::Error> {
+    fs::create_dir_all(CACHE_DIR)?;
+    for entry in fs::read_dir(CACHE_DIR)? {
+        let stale = entry?.path();
+        let is_dump = stale.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+            name.starts_with("syntaxes-") && name.ends_with(".packdump")
+        });
+        if is_dump {
+            fs::remove_file(stale)?;
+        }
+    }
+
+    syntect::dumps::dump_to_uncompressed_file(syntax_set, path).map_err(io::Error::other)
+}
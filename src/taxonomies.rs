@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::front_matter::FrontMatter;
+use crate::slug::slugify;
+
+
+/// A way of grouping posts, like tags, with a page per term
+pub(crate) struct Taxonomy {
+    pub(crate) name: String,
+    pub(crate) slug: String,
+    /// Sorted by slug
+    pub(crate) terms: Vec<Term>,
+}
+
+/// A term of a taxonomy, like the `rust` tag
+pub(crate) struct Term {
+    /// As first written in a post
+    pub(crate) name: String,
+    pub(crate) slug: String,
+    /// Indexes of the posts with the term
+    pub(crate) pages: Vec<usize>,
+}
+
+impl Taxonomy {
+    /// Output path of the page listing every term, like `tags/index.html`
+    pub(crate) fn url(&self) -> String {
+        format!("{}/index.html", self.slug)
+    }
+
+    /// Output path of the page listing the posts of `term`
+    pub(crate) fn term_url(&self, term: &Term) -> String {
+        format!("{}/{}/index.html", self.slug, term.slug)
+    }
+
+    /// Output path of the Atom feed of `term`
+    pub(crate) fn feed_url(&self, term: &Term) -> String {
+        format!("{}/{}/atom.xml", self.slug, term.slug)
+    }
+
+    /// The terms of the post at `index`
+    pub(crate) fn terms_of(&self, index: usize) -> impl Iterator<Item = &Term> {
+        self.terms.iter().filter(move |term| term.pages.contains(&index))
+    }
+}
+
+/// Groups posts by their terms for each taxonomy in `names`, which posts
+/// list in the front matter field of the same name, like `tags: [rust, web]`.
+///
+/// Terms are told apart by their slug, so `Rust` and `rust` are the same tag.
+/// Returns the taxonomies, and problems with the terms of posts by post index.
+pub(crate) fn collect(names: &[String], posts: &[&FrontMatter]) -> (Vec<Taxonomy>, Vec<(usize, String)>) {
+    let mut taxonomies = Vec::new();
+    let mut problems = Vec::new();
+
+    for name in names {
+        let mut terms: BTreeMap<String, Term> = BTreeMap::new();
+        // Only report each pair of names sharing a slug once
+        let mut reported = HashSet::new();
+
+        for (i, front_matter) in posts.iter().enumerate() {
+            let names = match front_matter.extra.get(name) {
+                None => continue,
+                Some(serde_yaml::Value::String(term)) => vec![term.as_str()],
+                Some(serde_yaml::Value::Sequence(terms)) if terms.iter().all(|term| term.is_string()) => {
+                    terms.iter().filter_map(|term| term.as_str()).collect()
+                }
+                Some(_) => {
+                    problems.push((i, format!("{name} must be a list of names, like {name}: [rust, web]")));
+                    continue;
+                }
+            };
+
+            for term_name in names {
+                let slug = slugify(term_name);
+                if slug.is_empty() {
+                    problems.push((i, format!("{name} term {term_name:?} has no letters or digits to name its page after")));
+                    continue;
+                }
+
+                let term = terms.entry(slug.clone()).or_insert_with(|| Term {
+                    name: term_name.trim().to_owned(),
+                    slug: slug.clone(),
+                    pages: Vec::new(),
+                });
+                let differs = term.name.to_lowercase() != term_name.trim().to_lowercase();
+                if differs && reported.insert((term.name.clone(), term_name.to_owned())) {
+                    problems.push((i, format!(
+                        "{name} term {term_name:?} shares the page {}/{slug}/ with {:?}",
+                        slugify(name),
+                        term.name,
+                    )));
+                }
+                if term.pages.last() != Some(&i) {
+                    term.pages.push(i);
+                }
+            }
+        }
+
+        taxonomies.push(Taxonomy {
+            name: name.clone(),
+            slug: slugify(name),
+            terms: terms.into_values().collect(),
+        });
+    }
+
+    (taxonomies, problems)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn front_matters(yaml: &[&str]) -> Vec<FrontMatter> {
+        yaml.iter().map(|yaml| serde_yaml::from_str(yaml).unwrap()).collect()
+    }
+
+    #[test]
+    fn terms_by_slug() {
+        let posts = front_matters(&["tags: [Rust, web]", "tags: rust", "title: Untagged", "tags: [Web, Rust, rust]"]);
+        let posts: Vec<_> = posts.iter().collect();
+        let (taxonomies, problems) = collect(&["tags".to_owned()], &posts);
+
+        assert_eq!(taxonomies.len(), 1);
+        let terms: Vec<_> = taxonomies[0].terms.iter()
+            .map(|term| (term.name.as_str(), term.slug.as_str(), term.pages.clone()))
+            .collect();
+        assert_eq!(terms, vec![("Rust", "rust", vec![0, 1, 3]), ("web", "web", vec![0, 3])]);
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn invalid_terms() {
+        let cases = [
+            ("tags: 3", "tags must be a list of names, like tags: [rust, web]"),
+            ("tags: [rust, [web]]", "tags must be a list of names, like tags: [rust, web]"),
+            ("tags: ['!!']", "tags term \"!!\" has no letters or digits to name its page after"),
+            ("tags: [C++, C]", "tags term \"C\" shares the page tags/c/ with \"C++\""),
+        ];
+        for (yaml, problem) in cases {
+            let posts = front_matters(&[yaml]);
+            let (_, problems) = collect(&["tags".to_owned()], &[&posts[0]]);
+            assert_eq!(problems, vec![(0, problem.to_owned())], "{yaml}");
+        }
+    }
+}
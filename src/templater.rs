@@ -1,52 +1,305 @@
 use std::hash::Hash;
 use std::{io, fs};
-use std::path::PathBuf;
-use std::{collections::HashSet, hash::Hasher, fmt::Display};
+use std::path::{Path, PathBuf};
+use std::ops::Range;
+use std::rc::Rc;
+use std::{collections::{HashSet, VecDeque}, hash::Hasher, fmt::Display};
 
 
 use pulldown_cmark;
-use syntect::{parsing::SyntaxSet, util::LinesWithEndings, highlighting::ThemeSet};
-
+use syntect::{parsing::{SyntaxReference, SyntaxSet}, util::LinesWithEndings, highlighting::ThemeSet};
+
+use crate::assets::{self, write_if_changed};
+use crate::code_block::CodeBlockInfo;
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::diagram::{CodeBlockProcessor, CodeBlockProcessors};
+use crate::feeds;
+use crate::front_matter::{self, Status};
+use crate::images::{Images, ProcessedImage};
+use crate::links::{self, Link};
+use crate::math;
+use crate::outputs::Outputs;
+use crate::paginator::{self, Paginator};
+use crate::placeholder::Placeholders;
+use crate::shortcodes::Shortcodes;
 use crate::push_str::push;
+use crate::related;
 use crate::push_str::escape_href;
 use crate::push_str::escape_html;
+use crate::sections::{Section, Sections};
+use crate::series::{self, Series};
+use crate::taxonomies::{self, Taxonomy};
+use crate::slug::Slugger;
+use crate::syntaxes;
+use crate::urls::{self, Urls};
 use super::push_str::PushStr;
 
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
+use serde::Serialize;
 pub(crate) struct  Markdown {
     pub(crate) title: String,
     pub (crate) body: String,
+    /// Plain text summary, for meta tags
     pub(crate) summary: String,
-    pub(crate) _outline: String,
+    /// Everything before the `<!-- more -->` marker, if the post has one
+    pub(crate) summary_html: Option<String>,
+    pub(crate) outline: String,
+    /// Headings that make up the outline
+    headings: Vec<OutlineHeading>,
+    pub(crate) stats: Stats,
+    /// Problems to report, with their location in the source
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    /// Hrefs of links and images, to be checked once every page is rendered
+    pub(crate) links: Vec<Link>,
+    /// Ids of headings, that links can point at
+    pub(crate) ids: Vec<String>,
+}
+
+/// Numbers about a post's content, shown in its header and in listings.
+#[derive(Default, Serialize)]
+pub(crate) struct Stats {
+    /// Words of prose, excluding code blocks
+    pub(crate) words: usize,
+    /// Characters of prose, excluding code blocks
+    pub(crate) characters: usize,
+    /// Words inside code blocks
+    pub(crate) code_words: usize,
+    pub(crate) code_blocks: usize,
+    pub(crate) images: usize,
+    /// Estimated reading time in minutes, never less than 1
+    pub(crate) reading_time: usize,
+}
+
+impl Stats {
+    fn count_text(&mut self, text: &str) {
+        self.words += text.split_whitespace().count();
+        self.characters += text.chars().filter(|c| !c.is_whitespace()).count();
+    }
+
+    fn count_code(&mut self, code: &str) {
+        self.code_words += code.split_whitespace().count();
+    }
+
+    fn add(&mut self, other: &Stats) {
+        self.words += other.words;
+        self.characters += other.characters;
+        self.code_words += other.code_words;
+        self.code_blocks += other.code_blocks;
+        self.images += other.images;
+    }
+
+    fn estimate_reading_time(&mut self, config: &Config) {
+        let minutes = self.words as f64 / config.words_per_minute.max(1) as f64
+            + self.code_words as f64 / config.code_words_per_minute.max(1) as f64;
+        self.reading_time = (minutes.ceil() as usize).max(1);
+    }
+}
+
+/// Everything loaded once per build that rendering markdown needs,
+/// and the post being rendered
+#[derive(Clone, Copy)]
+pub(crate) struct RenderContext<'a> {
+    pub(crate) config: &'a Config,
+    pub(crate) shortcodes: &'a Shortcodes,
+    pub(crate) code_blocks: &'a CodeBlockProcessors,
+    pub(crate) syntax_set: &'a SyntaxSet,
+    pub(crate) urls: &'a Urls,
+    pub(crate) images: &'a Images<'a>,
+    /// Path of the post relative to the content directory,
+    /// that links to other posts are relative to
+    pub(crate) source_path: &'a Path,
+}
+
+/// Heading ids handed out on a page, shared with the markdown of its shortcodes
+/// so that no two headings of the page end up with the same id.
+pub(crate) struct PageIds {
+    /// Generates ids for headings without an explicit `{#id}`
+    slugger: Slugger,
+    /// Explicit heading ids encountered so far, to reject duplicates
+    seen: HashSet<String>,
+}
+
+/// A heading of the outline, with its offset in the source
+/// so that headings from shortcodes end up in document order.
+struct OutlineHeading {
+    offset: usize,
+    /// The normalized heading level, in the range [2..6]
+    level: u8,
+    id: String,
+    html: String,
 }
 
-pub(crate) fn parse(source: &str) -> Markdown {
+fn options() -> pulldown_cmark::Options {
     // Enable extra specs besides default common_mark specs
-    let options = pulldown_cmark::Options::empty()
+    pulldown_cmark::Options::empty()
         | pulldown_cmark::Options::ENABLE_TABLES
         | pulldown_cmark::Options::ENABLE_HEADING_ATTRIBUTES
         | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
-        | pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION;
+        | pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION
+}
 
-    Renderer {
-        parser: pulldown_cmark::Parser::new_ext(source, options),
+pub(crate) fn parse(source: &str, context: &RenderContext<'_>) -> Markdown {
+    // Explicit `{#id}`s, including those inside shortcodes, are collected up front
+    // so that generated slugs never take an id that a later heading asks for.
+    let explicit_ids = pulldown_cmark::Parser::new_ext(source, options())
+        .filter_map(|event| match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading(_, Some(id), _)) => {
+                Some(id.to_owned())
+            }
+            _ => None,
+        })
+        .collect();
+    let mut ids = PageIds {
+        slugger: Slugger::new(explicit_ids),
+        seen: HashSet::new(),
+    };
+
+    let mut markdown = parse_nested(source, context, &mut ids);
+    markdown.headings.sort_by_key(|heading| heading.offset);
+    markdown.outline = outline(&markdown.headings);
+    markdown.stats.estimate_reading_time(context.config);
+    markdown
+}
+
+/// Renders markdown that is part of a page, like the inner markdown of a shortcode.
+/// The outline and reading time are left for `parse` to fill in.
+fn parse_nested(source: &str, context: &RenderContext<'_>, ids: &mut PageIds) -> Markdown {
+    let options = options();
+
+    // Shortcodes and math are rendered before the markdown itself.
+    // Diagnostics are moved back through each step to point into the original source.
+    let mut placeholders = Placeholders::default();
+    let mut diagnostics = Vec::new();
+    let mut nested = Vec::new();
+    let (source, shortcodes_map) = context.shortcodes.expand(
+        source,
+        options,
+        &mut placeholders,
+        &mut diagnostics,
+        |offset, inner| {
+            let mut markdown = parse_nested(inner, context, ids);
+            let body = std::mem::take(&mut markdown.body);
+            nested.push((offset, markdown));
+            body
+        },
+    );
+
+    let mut math_diagnostics = Vec::new();
+    let (source, math_map) = math::expand(&source, options, &mut placeholders, &mut math_diagnostics);
+    diagnostics.extend(math_diagnostics.into_iter().map(|diagnostic| {
+        Diagnostic::new(shortcodes_map.original_offset(diagnostic.offset), diagnostic.message)
+    }));
+    let source = &*source;
+
+    let has_more_marker = pulldown_cmark::Parser::new_ext(source, options)
+        .any(|event| matches!(event, pulldown_cmark::Event::Html(html) if is_more_marker(&html)));
+
+    let mut markdown = Renderer {
+        parser: pulldown_cmark::Parser::new_ext(source, options).into_offset_iter(),
+        pending: VecDeque::new(),
+        position: 0,
         title: String::new(),
         in_title: false,
         body: String::new(),
         summary: String::new(),
         in_summary: false,
+        summary_open: true,
+        has_more_marker,
+        summary_html: None,
         in_table_head: false,
+        in_figure: false,
+        blockquotes: Vec::new(),
         used_classes: HashSet::new(),
-        outline: String::new(),
+        headings: Vec::new(),
+        block_text: String::new(),
         outline_level: 1,
         in_heading: false,
-        syntax_set: &SYNTAX_SET,
+        heading: None,
+        page_ids: ids,
+        stats: Stats::default(),
+        placeholders: &placeholders,
+        diagnostics: Vec::new(),
+        links: Vec::new(),
+        ids: Vec::new(),
+        syntax_set: context.syntax_set,
+        context,
+    }
+    .render();
+
+    let original_offset = |offset| shortcodes_map.original_offset(math_map.original_offset(offset));
+    for diagnostic in &mut markdown.diagnostics {
+        diagnostic.offset = original_offset(diagnostic.offset);
+    }
+    for link in &mut markdown.links {
+        link.offset = original_offset(link.offset);
+    }
+    for heading in &mut markdown.headings {
+        heading.offset = original_offset(heading.offset);
+    }
+    markdown.diagnostics.extend(diagnostics);
+
+    // The inner markdown of shortcodes ends up in the same page
+    for (offset, inner) in nested {
+        markdown.diagnostics.extend(inner.diagnostics.into_iter().map(|diagnostic| {
+            Diagnostic::new(offset + diagnostic.offset, diagnostic.message)
+        }));
+        markdown.links.extend(inner.links.into_iter().map(|link| Link {
+            offset: offset + link.offset,
+            href: link.href,
+        }));
+        markdown.headings.extend(inner.headings.into_iter().map(|heading| OutlineHeading {
+            offset: offset + heading.offset,
+            ..heading
+        }));
+        markdown.ids.extend(inner.ids);
+        markdown.stats.add(&inner.stats);
     }
-    .render()
+    markdown.diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+
+    markdown
+}
+
+/// Nested `<ul>`s linking to each heading.
+/// Levels may only go one deeper at a time, a jump is treated as a subheading.
+fn outline(headings: &[OutlineHeading]) -> String {
+    let mut outline = String::new();
+    let mut outline_level = 1;
+
+    for heading in headings {
+        let level = heading.level.min(outline_level + 1);
+        if let Some(levels_down) = outline_level.checked_sub(level) {
+            outline.push_str("</li>");
+            for _ in 0..levels_down {
+                outline.push_str("</ul></li>");
+            }
+        } else {
+            outline.push_str("<ul>");
+        }
+        outline_level = level;
+
+        outline.push_str("<li><a href='#");
+        escape_href(&mut outline, &heading.id);
+        outline.push_str("'>");
+        outline.push_str(&heading.html);
+        outline.push_str("</a>");
+    }
+
+    // Close remaining opened tags
+    for _ in 0..outline_level - 1 {
+        outline.push_str("</li></ul>");
+    }
+
+    outline
 }
 
 struct Renderer<'a> {
-    parser: pulldown_cmark::Parser<'a, 'a>,
+    parser: pulldown_cmark::OffsetIter<'a, 'a>,
+    /// Events that were looked ahead at and must be handled before the parser's
+    pending: VecDeque<(pulldown_cmark::Event<'a>, Range<usize>)>,
+    /// Byte offset in the source of the event being rendered
+    position: usize,
     title: String,
     /// Whether we are currently writing to the title instead of body
     in_title: bool,
@@ -54,29 +307,79 @@ struct Renderer<'a> {
     summary: String,
     /// Whether we are currently writing to the summary
     in_summary: bool,
+    /// Whether following paragraphs still belong to the summary
+    summary_open: bool,
+    /// Whether the summary ends at a `<!-- more -->` marker
+    /// instead of after `Config::summary_length` characters
+    has_more_marker: bool,
+    summary_html: Option<String>,
     /// Whether we are in a `<thead>`
     /// Used to determine whether to output `<td>`s or `<th>`s
     in_table_head: bool,
+    /// Whether the current paragraph only holds an image, rendered as a figure
+    in_figure: bool,
+    /// For each opened blockquote, whether it is rendered as an admonition
+    blockquotes: Vec<bool>,
     /// Class names that need to be generated in the resulting CSS
     used_classes: HashSet<Classes>,
-    outline: String,
+    headings: Vec<OutlineHeading>,
+    /// Text of the current block, counted once the block ends
+    /// so that a word split by emphasis, like `foo**bar**`, counts once
+    block_text: String,
     /// The level of the currently opened heading `<li> in the outline.
     /// In the range [1..6]
     outline_level: u8,
     /// Whether we are in a `hN` tag.
     /// Used to determine whether to also write to the outline
     in_heading: bool,
+    /// The `hN` tag currently being written, whose opening tag is only
+    /// emitted once its text is known
+    heading: Option<OpenHeading>,
+    page_ids: &'a mut PageIds,
+    stats: Stats,
+    /// HTML of shortcodes and math, whose placeholders appear in the source
+    placeholders: &'a Placeholders,
+    diagnostics: Vec<Diagnostic>,
+    links: Vec<Link>,
+    ids: Vec<String>,
     syntax_set: &'a SyntaxSet,
+    context: &'a RenderContext<'a>,
+}
+
+struct OpenHeading {
+    /// The normalized heading level, in the range [2..6]
+    level: u8,
+    id: Option<String>,
+    /// Plain text of the heading, used to generate its slug
+    text: String,
+    /// Where the opening `<hN>` tag goes in the body
+    body_start: usize,
+    /// Offset of the heading in the source
+    offset: usize,
+    /// HTML of the heading, for its link in the outline
+    html: String,
 }
 
 impl<'a> Renderer<'a>{
     fn render(mut self) -> Markdown {
-        while let Some(event) =  self.parser.next(){
+        while let Some(event) = self.next_event() {
             match event {
-                pulldown_cmark::Event::Start(tag) => self.start_tag(tag),
-                pulldown_cmark::Event::End(tag) => self.end_tag(tag),
+                pulldown_cmark::Event::Start(tag) => {
+                    if !is_inline(&tag) {
+                        self.count_block_text();
+                    }
+                    self.start_tag(tag)
+                }
+                pulldown_cmark::Event::End(tag) => {
+                    if !is_inline(&tag) {
+                        self.count_block_text();
+                    }
+                    self.end_tag(tag)
+                }
                 pulldown_cmark::Event::Text(text) => {
                     self.push_summary(&text);
+                    self.push_heading_text(&text);
+                    self.count_text(&text);
                     escape_html(&mut self, &text)
                 }
                 pulldown_cmark::Event::Code(text) => {
@@ -88,22 +391,35 @@ impl<'a> Renderer<'a>{
                             None => (None, &*text),
                         };
                     
-                    if let Some(language) = language {
-                        self.syntax_highlight(language, code);
+                    let syntax = language.and_then(|language| self.find_syntax(language));
+                    if let Some(syntax) = syntax {
+                        let html = self.syntax_highlight(syntax, code);
+                        self.push_str(&html);
                     } else {
-                        escape_html(&mut self, &text);
+                        escape_html(&mut self, code);
                     }
                     self.push_summary(code);
+                    self.push_heading_text(code);
+                    self.count_text(code);
 
                     self.push_str("</code>");
                 }
-                pulldown_cmark::Event::Html(html) => self.push_str(&html),
+                pulldown_cmark::Event::Html(html) if is_more_marker(&html) => {
+                    self.summary_open = false;
+                    self.summary_html = Some(self.body.clone());
+                }
+                pulldown_cmark::Event::Html(html) => {
+                    let html = self.placeholders.substitute(&html);
+                    self.push_str(&html);
+                }
                 pulldown_cmark::Event::SoftBreak => {
                     self.push_summary(" ");
+                    self.count_text(" ");
                     self.push_str(" ");
                 }
                 pulldown_cmark::Event::HardBreak => {
                     self.push_summary(" ");
+                    self.count_text(" ");
                     self.push_str("<br>");
                 }
                 pulldown_cmark::Event::Rule => self.push_str("<hr>"),
@@ -115,16 +431,20 @@ impl<'a> Renderer<'a>{
 
         assert!(!self.in_table_head);
         assert!(!self.in_heading);
-
-        // Close remaining opened tags in the outline
-        for _ in 0..self.outline_level - 1 {
-            self.outline.push_str("</li></ul>");
-        }
+        self.count_block_text();
 
         if !self.used_classes.is_empty() {
+            // Sorted, as the order of a `HashSet` changes from one build to the next
+            let mut definitions: Vec<String> = self.used_classes.iter().map(|class| {
+                let mut definition = String::new();
+                class.write_definition(&mut definition);
+                definition
+            }).collect();
+            definitions.sort_unstable();
+
             self.push_str("<style>");
-            for class in &self.used_classes {
-                class.write_definition(&mut self.body);
+            for definition in &definitions {
+                self.push_str(definition);
             }
             self.push_str("</style>");
         }
@@ -132,8 +452,14 @@ impl<'a> Renderer<'a>{
         Markdown { 
             title: self.title, 
             body: self.body, 
-            summary: self.summary, 
-            _outline: self.outline, 
+            summary: truncate_words(self.summary.trim(), self.context.config.summary_length),
+            summary_html: self.summary_html,
+            outline: String::new(),
+            headings: self.headings,
+            stats: self.stats,
+            diagnostics: self.diagnostics,
+            links: self.links,
+            ids: self.ids,
         }
 
     }
@@ -142,10 +468,11 @@ impl<'a> Renderer<'a>{
 
         match tag {
             pulldown_cmark::Tag::Paragraph => {
-                if self.summary.is_empty() {
-                    self.in_summary = true;
+                self.in_summary = self.summary_open;
+                self.in_figure = self.is_standalone_image();
+                if !self.in_figure {
+                    self.push_str("<p>");
                 }
-                self.push_str("<p>");
             }
             pulldown_cmark::Tag::Heading(pulldown_cmark::HeadingLevel::H1, id, classes) => {
                 if !classes.is_empty() || id.is_some() {
@@ -160,41 +487,33 @@ impl<'a> Renderer<'a>{
 
                 let mut level = level as u8;
 
-                // Update the outline and normalize heading levels.
-                if let Some(levels_down) = self.outline_level.checked_sub(level) {
-                    self.outline.push_str("</li>");
-                    for _ in 0..levels_down {
-                        self.outline.push_str("</ul></li>");
-                    }
-                } else {
-                    self.outline.push_str("<ul>");
-
-                    if level != self.outline_level + 1 {
-                        let _outline_level = self.outline_level;
-                        // self.error(format_args!(
-                        //     "heading level jump: {outline_level} to {level}"
-                        // ));
-                        level = self.outline_level + 1;
-                    }
+                // Normalize heading levels.
+                if level > self.outline_level + 1 {
+                    let _outline_level = self.outline_level;
+                    // self.error(format_args!(
+                    //     "heading level jump: {outline_level} to {level}"
+                    // ));
+                    level = self.outline_level + 1;
                 }
 
-                self.outline.push_str("<li><a href='#");
                 if let Some(id) = id {
-                    escape_href(&mut self.outline, id);
+                    if !self.page_ids.seen.insert(id.to_owned()) {
+                        self.error(format_args!("duplicate heading id {id}"));
+                    }
                 }
-                self.outline.push_str("'>");
+
                 self.outline_level = level;
 
-                if let Some(id) = id {
-                    push!(self, "<h{level} id='");
-                    escape_html(self, id);
-                    self.push_str("'><a href='#");
-                    escape_html(self, id);
-                    self.push_str("' class='anchor'></a>");
-                } else {
-                    // self.error("heading does not have id");
-                    push!(self, "<h{level}>");
-                }
+                // The opening tag is written in `end_tag`,
+                // once the heading text is known to generate its id.
+                self.heading = Some(OpenHeading {
+                    level,
+                    id: id.map(str::to_owned),
+                    text: String::new(),
+                    body_start: self.body.len(),
+                    offset: self.position,
+                    html: String::new(),
+                });
 
                 self.in_heading = true;
             }
@@ -223,14 +542,35 @@ impl<'a> Renderer<'a>{
                     false => "<td>",
                 });
             }
-            pulldown_cmark::Tag::BlockQuote => self.push_str("<blockquote>"),
-            pulldown_cmark::Tag::CodeBlock(kind) => {
-                self.push_str("<pre class='scode'><code>");
+            pulldown_cmark::Tag::BlockQuote => {
+                let admonition = self.admonition_marker();
+                self.blockquotes.push(admonition.is_some());
 
-                let language = match kind {
-                    pulldown_cmark::CodeBlockKind::Fenced(lang) if lang.is_empty() => None,
-                    pulldown_cmark::CodeBlockKind::Fenced(lang) => Some(lang),
-                    pulldown_cmark::CodeBlockKind::Indented => None,
+                let Some((admonition, title)) = admonition else {
+                    self.push_str("<blockquote>");
+                    return;
+                };
+
+                push!(self, "<aside class='admonition {}' role='note'>", admonition.class_name());
+                self.push_str("<p class='admonition-title'>");
+                self.push_str("<span class='admonition-icon' aria-hidden='true'></span>");
+                match title {
+                    Some(title) => escape_html(self, &title),
+                    None => self.push_str(admonition.title()),
+                }
+                self.push_str("</p>");
+            }
+            pulldown_cmark::Tag::CodeBlock(kind) => {
+                let info = match kind {
+                    pulldown_cmark::CodeBlockKind::Fenced(info) => {
+                        let mut errors = Vec::new();
+                        let info = CodeBlockInfo::parse(&info, &mut errors);
+                        for msg in errors {
+                            self.error(msg);
+                        }
+                        info
+                    }
+                    pulldown_cmark::CodeBlockKind::Indented => CodeBlockInfo::default(),
                 };
 
                 fn event_text(
@@ -244,19 +584,50 @@ impl<'a> Renderer<'a>{
                     }
                 }
 
-                if let Some(language) = language {
-                    let mut code = String::new();
-                    while let Some(part) = self.parser.next().and_then(event_text) {
-                        code.push_str(&part);
+                let mut code = String::new();
+                while let Some(part) = self.next_event().and_then(event_text) {
+                    code.push_str(&part);
+                }
+
+                let processor = info
+                    .language
+                    .as_deref()
+                    .and_then(|language| self.context.code_blocks.get(language));
+                if let Some(processor) = processor {
+                    self.process_code_block(processor, &code);
+                    return;
+                }
+                let code_blocks = self.context.code_blocks;
+                if info.language.as_deref().is_some_and(|language| code_blocks.is_unrendered_diagram(language)) {
+                    self.push_plain_code(&code);
+                    return;
+                }
+
+                self.stats.code_blocks += 1;
+                self.stats.count_code(&code);
+
+                let syntax = info
+                    .language
+                    .as_deref()
+                    .and_then(|language| self.find_syntax(language));
+                let html = match syntax {
+                    Some(syntax) => self.syntax_highlight(syntax, &code),
+                    None => {
+                        let mut html = String::new();
+                        escape_html(&mut html, &code);
+                        html
                     }
-                    self.syntax_highlight(&language, &code);
+                };
+
+                info.write_figure_start(self, syntax.map(|syntax| syntax.name.as_str()));
+                if info.needs_lines() {
+                    self.push_str("<pre class='scode lines'><code>");
+                    info.write_lines(self, &html);
                 } else {
-                    while let Some(part) = self.parser.next().and_then(event_text) {
-                        escape_html(self, &part);
-                    }
+                    self.push_str("<pre class='scode'><code>");
+                    self.push_str(&html);
                 }
-
-                self.push_str("</code></pre>");
+                self.push_str("</code></pre></figure>");
             }
             pulldown_cmark::Tag::List(Some(1)) => self.push_str("<ol>"),
             pulldown_cmark::Tag::List(Some(start)) => {
@@ -271,30 +642,95 @@ impl<'a> Renderer<'a>{
                 self.error("email links are not supported yet");
             }
             pulldown_cmark::Tag::Link(_type, href, title) => {
+                let (href, check) = match self.context.urls.resolve(self.context.source_path, &href) {
+                    Some(Ok(url)) => (url.into(), true),
+                    // Reported here, the link checker does not need to do it again
+                    Some(Err(msg)) => {
+                        self.error(msg);
+                        (href, false)
+                    }
+                    None => (href, true),
+                };
+                if check {
+                    self.links.push(Link { offset: self.position, href: href.to_string() });
+                }
+                let (title, plain) = links::plain_marker(&title);
                 self.push_str("<a href='");
                 escape_href(self, &href);
                 if !title.is_empty() {
                     self.push_str("' title='");
-                    escape_html(self, &title);
+                    escape_html(self, title);
                 }
-                self.push_str("'>");
+                self.push_str("'");
+                if let Some(host) = links::external_host(&href, &self.context.config.base_url) {
+                    self.context.config.external_links.write_attributes(self, &host, plain);
+                }
+                self.push_str(">");
             }
             pulldown_cmark::Tag::Image(_, url, title) => {
-                self.push_str("<img src='");
-                escape_href(self, &url);
-                self.push_str("' alt='");
-                while let Some(event) = self.parser.next() {
+                self.stats.images += 1;
+                let image = self.process_image(&url);
+
+                // The alt text is the plain text of the description,
+                // without its markup, math or shortcodes
+                let mut alt = String::new();
+                let mut depth = 0;
+                while let Some(event) = self.next_event() {
                     match event {
-                        pulldown_cmark::Event::End(_) => break,
-                        pulldown_cmark::Event::Text(text) => escape_html(self, &text),
-                        _ => unreachable!(),
+                        pulldown_cmark::Event::Start(_) => depth += 1,
+                        pulldown_cmark::Event::End(_) if depth == 0 => break,
+                        pulldown_cmark::Event::End(_) => depth -= 1,
+                        pulldown_cmark::Event::Text(text) | pulldown_cmark::Event::Code(text) => alt.push_str(&text),
+                        pulldown_cmark::Event::SoftBreak | pulldown_cmark::Event::HardBreak => alt.push(' '),
+                        _ => {}
                     }
                 }
-                if !title.is_empty() {
+                let alt = alt.split_whitespace().collect::<Vec<_>>().join(" ");
+
+                if self.in_figure {
+                    self.push_str("<figure class='image'>");
+                }
+
+                let root = self.root();
+                let sizes = &self.context.config.images.sizes;
+                let in_picture = image.as_ref().is_some_and(|image| !image.webp_variants.is_empty());
+                if let (true, Some(image)) = (in_picture, &image) {
+                    self.push_str("<picture>");
+                    image.write_sources(self, &root, sizes);
+                }
+
+                self.push_str("<img src='");
+                match &image {
+                    Some(image) => {
+                        escape_href(self, &root);
+                        escape_href(self, &image.src);
+                    }
+                    None => escape_href(self, &url),
+                }
+                self.push_str("' alt='");
+                escape_html(self, &alt);
+                // Figures show the title as their caption instead
+                if !title.is_empty() && !self.in_figure {
                     self.push_str("' title='");
                     escape_html(self, &title);
                 }
-                self.push_str("'>");
+                self.push_str("'");
+                if let Some(image) = &image {
+                    image.write_attributes(self, &root, sizes);
+                }
+                self.push_str(" loading='lazy' decoding='async'>");
+                if in_picture {
+                    self.push_str("</picture>");
+                }
+
+                if self.in_figure {
+                    if !title.is_empty() {
+                        self.push_str("<figcaption>");
+                        self.push_caption(&title);
+                        self.push_str("</figcaption>");
+                    }
+                    self.push_str("</figure>");
+                }
             }
             // We do not enable this extension
             pulldown_cmark::Tag::FootnoteDefinition(_) => unreachable!(),
@@ -305,20 +741,52 @@ impl<'a> Renderer<'a>{
      fn end_tag(&mut self, tag: pulldown_cmark::Tag<'a>) {
         match tag {
             pulldown_cmark::Tag::Paragraph => {
-                self.push_str("</p>");
+                if !std::mem::take(&mut self.in_figure) {
+                    self.push_str("</p>");
+                }
+                if self.in_summary {
+                    self.summary.push(' ');
+                    // Without a marker, keep collecting paragraphs
+                    // until there is enough text to truncate
+                    if !self.has_more_marker
+                        && self.summary.chars().count() >= self.context.config.summary_length
+                    {
+                        self.summary_open = false;
+                    }
+                }
                 self.in_summary = false;
             }
             pulldown_cmark::Tag::Heading(pulldown_cmark::HeadingLevel::H1, _id, _classes) => {
                 self.in_title = false;
             }
-            pulldown_cmark::Tag::Heading(level, _id, _classes) => {
+            pulldown_cmark::Tag::Heading(_level, _id, _classes) => {
                 self.in_heading = false;
 
-                self.outline.push_str("</a>");
+                let heading = self.heading.take().unwrap();
+                let id = match heading.id {
+                    Some(id) => id,
+                    None => self.page_ids.slugger.slug(&heading.text),
+                };
 
-                self.push_str("</");
-                push!(self, "{}", level);
-                self.push_str(">");
+                let mut href = String::new();
+                escape_href(&mut href, &id);
+
+                let mut open_tag = String::new();
+                push!(open_tag, "<h{} id='", heading.level);
+                escape_html(&mut open_tag, &id);
+                open_tag.push_str("'><a href='#");
+                open_tag.push_str(&href);
+                open_tag.push_str("' class='anchor'></a>");
+                self.body.insert_str(heading.body_start, &open_tag);
+                self.headings.push(OutlineHeading {
+                    offset: heading.offset,
+                    level: heading.level,
+                    id: id.clone(),
+                    html: heading.html,
+                });
+                self.ids.push(id);
+
+                push!(self, "</h{}>", heading.level);
             }
             pulldown_cmark::Tag::Table(_) => {
                 self.push_str("</tbody></table>");
@@ -336,7 +804,12 @@ impl<'a> Renderer<'a>{
                     false => "</td>",
                 });
             }
-            pulldown_cmark::Tag::BlockQuote => self.push_str("</blockquote>"),
+            pulldown_cmark::Tag::BlockQuote => {
+                match self.blockquotes.pop().unwrap() {
+                    true => self.push_str("</aside>"),
+                    false => self.push_str("</blockquote>"),
+                }
+            }
             pulldown_cmark::Tag::List(Some(_)) => self.push_str("</ol>"),
             pulldown_cmark::Tag::List(None) => self.push_str("</ul>"),
             pulldown_cmark::Tag::Item => self.push_str("</li>"),
@@ -353,15 +826,196 @@ impl<'a> Renderer<'a>{
         }
     }
 
-    fn syntax_highlight(&mut self, language: &str, code: &str) {
-        let Some(syntax) = self.syntax_set.find_syntax_by_token(language) else {
-            self.error(format_args!("no known language {language}"));
-            self.push_str(code);
+    fn next_event(&mut self) -> Option<pulldown_cmark::Event<'a>> {
+        let (event, range) = self.pending.pop_front().or_else(|| self.parser.next())?;
+        self.position = range.start;
+        Some(event)
+    }
+
+    /// Checks whether the blockquote that was just opened starts with
+    /// a GitHub-style `[!NOTE]` line, consuming that line if so.
+    /// The rest of the line, if any, is used as the title.
+    fn admonition_marker(&mut self) -> Option<(Admonition, Option<String>)> {
+        let mut lookahead = Vec::new();
+        let mut line = String::new();
+
+        let marker = match self.next_event() {
+            Some(event @ pulldown_cmark::Event::Start(pulldown_cmark::Tag::Paragraph)) => {
+                lookahead.push((event, self.position..self.position));
+                loop {
+                    match self.next_event() {
+                        Some(pulldown_cmark::Event::Text(text)) => {
+                            line.push_str(&text);
+                            lookahead.push((pulldown_cmark::Event::Text(text), self.position..self.position));
+                        }
+                        Some(event) => {
+                            let end_of_line = matches!(
+                                event,
+                                pulldown_cmark::Event::SoftBreak
+                                    | pulldown_cmark::Event::End(pulldown_cmark::Tag::Paragraph)
+                            );
+                            lookahead.push((event, self.position..self.position));
+                            break if end_of_line { Admonition::parse(&line) } else { None };
+                        }
+                        None => break None,
+                    }
+                }
+            }
+            Some(event) => {
+                lookahead.push((event, self.position..self.position));
+                None
+            }
+            None => None,
+        };
+
+        if marker.is_some() {
+            // The paragraph goes on after the marker line
+            if let Some((pulldown_cmark::Event::SoftBreak, range)) = lookahead.last() {
+                let start = (pulldown_cmark::Event::Start(pulldown_cmark::Tag::Paragraph), range.clone());
+                self.pending.push_front(start);
+            }
+        } else {
+            for event in lookahead.into_iter().rev() {
+                self.pending.push_front(event);
+            }
+        }
+
+        marker
+    }
+
+    /// Renders a code block with its processor, keeping the source
+    /// available in a `<details>` in case the result is unreadable.
+    fn process_code_block(&mut self, processor: &dyn CodeBlockProcessor, code: &str) {
+        match processor.process(code) {
+            Ok(html) => {
+                self.stats.images += 1;
+                self.push_str("<figure class='diagram'>");
+                self.push_str(&html);
+                self.push_str("<details><summary>Source</summary><pre class='scode'><code>");
+                escape_html(self, code);
+                self.push_str("</code></pre></details></figure>");
+            }
+            Err(msg) => {
+                self.error(msg);
+                self.push_plain_code(code);
+            }
+        }
+    }
+
+    /// Renders a code block as it is, without highlighting
+    fn push_plain_code(&mut self, code: &str) {
+        self.stats.code_blocks += 1;
+        self.stats.count_code(code);
+        self.push_str("<pre class='scode'><code>");
+        escape_html(self, code);
+        self.push_str("</code></pre>");
+    }
+
+    /// Checks whether the paragraph that was just opened only holds an image,
+    /// which is then rendered as a `<figure>` instead of inside a `<p>`.
+    fn is_standalone_image(&mut self) -> bool {
+        let mut lookahead = Vec::new();
+        let mut next = |renderer: &mut Self| {
+            let event = renderer.next_event()?;
+            lookahead.push((event.clone(), renderer.position..renderer.position));
+            Some(event)
+        };
+
+        let standalone = matches!(next(self), Some(pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image(..))))
+            && {
+                // The alt text, which may have markup of its own
+                let mut depth = 0;
+                loop {
+                    match next(self) {
+                        Some(pulldown_cmark::Event::Start(_)) => depth += 1,
+                        Some(pulldown_cmark::Event::End(_)) if depth == 0 => break true,
+                        Some(pulldown_cmark::Event::End(_)) => depth -= 1,
+                        Some(_) => {}
+                        None => break false,
+                    }
+                }
+            }
+            && matches!(next(self), Some(pulldown_cmark::Event::End(pulldown_cmark::Tag::Paragraph)));
+
+        for event in lookahead.into_iter().rev() {
+            self.pending.push_front(event);
+        }
+        standalone
+    }
+
+    /// Renders the markdown of an image title into a caption
+    fn push_caption(&mut self, title: &str) {
+        // Math in the title was already rendered along with the rest of the post
+        let title = self.placeholders.substitute(title);
+        if !is_single_paragraph(&title) {
+            self.error("image titles can only hold inline markdown");
+            escape_html(self, &title);
             return;
+        }
+        let caption = parse(&title, self.context);
+
+        // Problems in the title are reported at the image
+        self.diagnostics.extend(caption.diagnostics.into_iter().map(|diagnostic| {
+            Diagnostic::new(self.position, diagnostic.message)
+        }));
+        self.links.extend(caption.links.into_iter().map(|link| Link {
+            offset: self.position,
+            href: link.href,
+        }));
+
+        let body = caption.body.trim();
+        let inline = body.strip_prefix("<p>").and_then(|body| body.strip_suffix("</p>"));
+        self.push_str(inline.unwrap_or(body));
+    }
+
+    /// Processes local images, leaving images from other sites untouched
+    fn process_image(&mut self, url: &str) -> Option<Rc<ProcessedImage>> {
+        let path = match links::classify(url) {
+            links::Target::Internal { path, .. } if !path.is_empty() => links::percent_decode(path),
+            _ => {
+                self.links.push(Link { offset: self.position, href: url.to_owned() });
+                return None;
+            }
         };
 
-        
-         let mut generator = syntect::html::ClassedHTMLGenerator::new_with_class_style(
+        // The static folder is copied to the root of the site
+        let config = self.context.config;
+        let source = match path.strip_prefix('/') {
+            Some(path) => config.static_dir.join(path),
+            None => config.content_dir.join(self.context.source_path.parent().unwrap_or(Path::new(""))).join(path),
+        };
+
+        match self.context.images.process(&source) {
+            Ok(image) => {
+                let href = format!("{}{}", self.root(), image.src);
+                self.links.push(Link { offset: self.position, href });
+                Some(image)
+            }
+            Err(msg) => {
+                self.error(msg);
+                None
+            }
+        }
+    }
+
+    /// The relative link from the page to the root of the site
+    fn root(&self) -> String {
+        urls::root(self.context.urls.output_path(self.context.source_path).unwrap_or(""))
+    }
+
+    fn find_syntax(&mut self, language: &str) -> Option<&'a SyntaxReference> {
+        let aliases = &self.context.config.language_aliases;
+        let token = aliases.get(language).map_or(language, String::as_str);
+        let syntax = self.syntax_set.find_syntax_by_token(token);
+        if syntax.is_none() {
+            self.error(format_args!("no known language {language}"));
+        }
+        syntax
+    }
+
+    /// Returns the code as HTML with syntect's classed spans
+    fn syntax_highlight(&self, syntax: &SyntaxReference, code: &str) -> String {
+        let mut generator = syntect::html::ClassedHTMLGenerator::new_with_class_style(
             syntax,
             self.syntax_set,
             SYNTECT_CLASS_STYLE,
@@ -372,13 +1026,14 @@ impl<'a> Renderer<'a>{
                 .expect("thanks syntect, really good API design where you return a `Result` but don’t specify when it can even fail");
         }
 
-        self.push_str(&generator.finalize());
+        generator.finalize()
     }
 
     fn error(&mut self, msg: impl Display) {
         self.push_str("<span style='color:red'>");
         push!(self, "{}", msg);
         self.push_str("</span>");
+        self.diagnostics.push(Diagnostic::new(self.position, msg));
     }
 
     fn push_summary(&mut self, s: &str) {
@@ -387,6 +1042,78 @@ impl<'a> Renderer<'a>{
         }
     }
 
+    fn count_text(&mut self, s: &str) {
+        // The title is not part of the post's content
+        if !self.in_title {
+            self.block_text.push_str(s);
+        }
+    }
+
+    fn count_block_text(&mut self) {
+        let text = std::mem::take(&mut self.block_text);
+        self.stats.count_text(&text);
+    }
+
+    fn push_heading_text(&mut self, s: &str) {
+        if let Some(heading) = &mut self.heading {
+            heading.text.push_str(s);
+        }
+    }
+
+}
+
+/// Whether `tag` is markup within a block, that does not separate words
+fn is_inline(tag: &pulldown_cmark::Tag) -> bool {
+    matches!(
+        tag,
+        pulldown_cmark::Tag::Emphasis
+            | pulldown_cmark::Tag::Strong
+            | pulldown_cmark::Tag::Strikethrough
+            | pulldown_cmark::Tag::Link(..)
+            | pulldown_cmark::Tag::Image(..)
+    )
+}
+
+/// Whether `markdown` is a single paragraph, whose content fits in a `<figcaption>`
+fn is_single_paragraph(markdown: &str) -> bool {
+    let mut depth = 0;
+    let mut paragraphs = 0;
+    for event in pulldown_cmark::Parser::new_ext(markdown, options()) {
+        match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Paragraph) if depth == 0 => {
+                paragraphs += 1;
+                depth += 1;
+            }
+            pulldown_cmark::Event::Start(_) if depth == 0 => return false,
+            pulldown_cmark::Event::Start(_) => depth += 1,
+            pulldown_cmark::Event::End(_) => depth -= 1,
+            // Blocks without content, like rules and HTML blocks
+            _ if depth == 0 => return false,
+            _ => {}
+        }
+    }
+    paragraphs == 1
+}
+
+fn is_more_marker(html: &str) -> bool {
+    html.trim() == "<!-- more -->"
+}
+
+/// Shortens `text` to at most `limit` characters without cutting words in half.
+fn truncate_words(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_owned();
+    }
+
+    let cut = text.char_indices().nth(limit).map_or(text.len(), |(i, _)| i);
+    let truncated = match text[..cut].rfind(char::is_whitespace) {
+        Some(space) if !text[cut..].starts_with(char::is_whitespace) => &text[..space],
+        _ => &text[..cut],
+    };
+
+    let mut truncated = truncated.trim_end().trim_end_matches([',', ';', ':']).to_owned();
+    truncated.push('…');
+    truncated
 }
 
 impl PushStr for Renderer<'_> {
@@ -395,13 +1122,60 @@ impl PushStr for Renderer<'_> {
             self.title.push_str(s);
         } else {
             self.body.push_str(s);
-            if self.in_heading {
-                self.outline.push_str(s);
+            if let Some(heading) = &mut self.heading {
+                heading.html.push_str(s);
             }
         }
     }
 }
 
+#[derive(Clone, Copy)]
+enum Admonition {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl Admonition {
+    fn parse(line: &str) -> Option<(Self, Option<String>)> {
+        let (kind, title) = line.trim().strip_prefix("[!")?.split_once(']')?;
+
+        let admonition = match &*kind.to_ascii_uppercase() {
+            "NOTE" => Self::Note,
+            "TIP" => Self::Tip,
+            "IMPORTANT" => Self::Important,
+            "WARNING" => Self::Warning,
+            "CAUTION" => Self::Caution,
+            _ => return None,
+        };
+
+        let title = title.trim();
+        Some((admonition, (!title.is_empty()).then(|| title.to_owned())))
+    }
+
+    fn class_name(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Tip => "tip",
+            Self::Important => "important",
+            Self::Warning => "warning",
+            Self::Caution => "caution",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::Tip => "Tip",
+            Self::Important => "Important",
+            Self::Warning => "Warning",
+            Self::Caution => "Caution",
+        }
+    }
+}
+
 struct TableAlignments(Vec<pulldown_cmark::Alignment>);
 
 impl TableAlignments {
@@ -471,8 +1245,6 @@ impl Classes {
 const SYNTECT_CLASS_STYLE: syntect::html::ClassStyle =
     syntect::html::ClassStyle::SpacedPrefixed { prefix: "s" };
 
-static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
-
 static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
     let mut theme_set = ThemeSet::default();
     theme_set.add_from_folder("templates/code_themes").expect("Failed to load Themes!");
@@ -485,100 +1257,627 @@ static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
 
 use syntect::highlighting::{Theme, Color};
 
-fn generate_css_for_theme(_theme: &Theme) -> String {
+/// The stylesheet for highlighted code, with the light and dark themes
+/// from `Config` applied according to the reader's preferred color scheme.
+fn generate_syntax_css(config: &Config) -> Result<String, io::Error> {
+    let mut css = String::new();
+
+    let themes = [("light", &config.code_theme_light), ("dark", &config.code_theme_dark)];
+    for (scheme, theme_name) in themes {
+        let theme = THEME_SET.themes.get(theme_name).ok_or_else(|| {
+            let mut available: Vec<_> = THEME_SET.themes.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no code theme {theme_name} in templates/code_themes, available: {}", available.join(", ")),
+            )
+        })?;
+
+        push!(css, "@media (prefers-color-scheme: {scheme}) {{\n");
+        css.push_str(&generate_css_for_theme(theme)?);
+        css.push_str("}\n");
+    }
 
-    let css = String::new();
+    Ok(css)
+}
 
+/// CSS for the `s`-prefixed classes of `SYNTECT_CLASS_STYLE`,
+/// plus the line highlight and gutter colors of the theme.
+fn generate_css_for_theme(theme: &Theme) -> Result<String, io::Error> {
+    let mut css = syntect::html::css_for_theme_with_class_style(theme, SYNTECT_CLASS_STYLE)
+        .map_err(io::Error::other)?;
 
+    if let Some(color) = theme.settings.line_highlight {
+        push!(css, ".scode .line.hl {{ background-color: {}; }}\n", color_to_css(color));
+    }
+    if let Some(color) = theme.settings.gutter_foreground {
+        push!(css, ".scode .lineno::before {{ color: {}; opacity: 1; }}\n", color_to_css(color));
+    }
 
-    // if let Some(background) = theme.settings.background {
-    //     css.push_str(&format!("body {{ background-color: {}; }}\n", color_to_css(background)));
-    // }
+    Ok(css)
+}
 
-    // if let Some(foreground) = theme.settings.foreground {
-    //     css.push_str(&format!("body {{ color: {}; }}\n", color_to_css(foreground)));
-    // }
+fn color_to_css(color: Color) -> String {
+    if color.a == 0xFF {
+        format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", color.r, color.g, color.b, color.a)
+    }
+}
 
+/// Builds the site to `blog/`. Drafts, scheduled and expired posts
+/// are only built, with a badge, when `drafts` is set.
+pub(crate) fn generate_html(posts: &[(PathBuf, String)], config: &Config, drafts: bool) -> Result<(), io::Error> {
 
-    css
-}
+    let output_dir = PathBuf::from("blog");
 
-fn _color_to_css(color: Color) -> String {
-    format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b)
-}
+    fs::create_dir_all(&output_dir)?;
 
+    // Every post's URL must be known before rendering any of them,
+    // for links between posts
+    let mut sources = Vec::new();
+    for (path, content) in posts {
+        let (front_matter, markdown) = front_matter::split(content)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+        let source_path = path.strip_prefix(&config.content_dir).unwrap_or(path);
+        sources.push((source_path, front_matter, markdown));
+    }
+    let mut urls = Urls::new(
+        sources.iter().map(|(source_path, front_matter, _)| (*source_path, front_matter)),
+        config.pretty_urls,
+    )?;
+
+    // What each post wrote, so that skipping it later removes exactly that
+    let previous_outputs = Outputs::load()?;
+    let mut outputs = Outputs::default();
+
+    let now = Utc::now();
+    let mut included = Vec::new();
+    let mut skipped = Vec::new();
+    for (post, source) in posts.iter().zip(sources) {
+        let (status, problems) = source.1.status(now);
+        for msg in problems {
+            eprintln!("warning: {}: {msg}", post.0.display());
+        }
+        let unpublished = match status {
+            Status::Published => None,
+            Status::Draft => Some("draft".to_owned()),
+            Status::Scheduled(date) => Some(format!("scheduled for {}", date.format("%Y-%m-%d %H:%M"))),
+            Status::Expired(date) => Some(format!("expired on {}", date.format("%Y-%m-%d %H:%M"))),
+        };
 
-fn append_css_to_file(css: &str, file_path: &PathBuf) -> std::io::Result<()> {
-    use std::fs::OpenOptions;
-    use std::io::Write;
+        match unpublished {
+            Some(reason) if !drafts => {
+                urls.unpublish(source.0);
+                previous_outputs.remove(source.0, &output_dir)?;
+                skipped.push((&post.0, reason));
+            }
+            unpublished => included.push((post, source, unpublished)),
+        }
+    }
+    let (posts, sources): (Vec<_>, Vec<_>) = included
+        .into_iter()
+        .map(|(post, source, unpublished)| (post, (source, unpublished)))
+        .unzip();
+
+    let shortcodes = Shortcodes::load(Path::new("templates/shortcodes"))?;
+    let code_blocks = CodeBlockProcessors::new(config);
+    let syntax_set = syntaxes::load(config)?;
+    let images = Images::new(&output_dir, &config.images);
+    let site_context = RenderContext {
+        config,
+        shortcodes: &shortcodes,
+        code_blocks: &code_blocks,
+        syntax_set: &syntax_set,
+        urls: &urls,
+        images: &images,
+        source_path: Path::new(""),
+    };
+
+    // Partials are registered as templates too, but cannot render a page on their own
+    let (handlebars, page_templates) = load_templates(Path::new("templates"))?;
+
+    // Copied first, so that generated files win over static ones
+    if config.static_dir.is_dir() {
+        assets::copy_dir(&config.static_dir, &output_dir, &|_| false)?;
+    }
 
-    let mut file = OpenOptions::new().append(true).open(file_path)?;
-    file.write_all(css.as_bytes())?;
-    Ok(())
-}
+    // The stylesheet is put together in memory, so that builds never modify
+    // the templates and always produce the same output
+    let mut posts_css = fs::read_to_string("templates/posts.css")?;
+    if !posts_css.ends_with('\n') {
+        posts_css.push('\n');
+    }
+    posts_css.push_str(&generate_syntax_css(config)?);
+    write_if_changed(&output_dir.join("posts.css"), &posts_css)?;
 
+    if config.copy_code_button {
+        let script = fs::read("templates/copy-code.js")?;
+        write_if_changed(&output_dir.join("copy-code.js"), &script)?;
+    }
 
+    // Links are checked once every page is known
+    let mut pages = Vec::new();
+    // Posts are all rendered before applying templates, as listings need
+    // the titles and summaries of other posts
+    let mut rendered = Vec::new();
+
+    for ((path, content), ((source_path, front_matter, markdown), unpublished)) in posts.iter().zip(&sources) {
+       let front_matter_len = content.len() - markdown.len();
+       let context = RenderContext { source_path, ..site_context };
+       let markdown = parse(markdown, &context);
+
+       for diagnostic in &markdown.diagnostics {
+           let diagnostic = Diagnostic::new(front_matter_len + diagnostic.offset, &diagnostic.message);
+           let (line, column) = diagnostic.location(content);
+           eprintln!("warning: {}:{line}:{column}: {}", path.display(), diagnostic.message);
+       }
+
+       let url = urls.output_path(source_path).unwrap().to_owned();
+       if let Some(bundle) = urls::bundle_dir(source_path) {
+           let page_dir = Path::new(&url).parent().unwrap_or(Path::new(""));
+           let is_markdown = |path: &Path| path.extension().is_some_and(|e| e == "md");
+           let copied = assets::copy_dir(&config.content_dir.join(bundle), &output_dir.join(page_dir), &is_markdown)?;
+           for file in copied {
+               if let Ok(file) = file.strip_prefix(&output_dir) {
+                   outputs.record(source_path, file.to_string_lossy());
+               }
+           }
+       }
+       outputs.record(source_path, url.clone());
+
+       pages.push(links::Page {
+           url: url.clone(),
+           ids: markdown.ids.into_iter().collect(),
+           links: markdown.links.into_iter().map(|link| Link {
+               offset: front_matter_len + link.offset,
+               href: link.href,
+           }).collect(),
+       });
 
-pub(crate) fn generate_html(posts: &[(PathBuf, String)]) -> Result<(), io::Error> {
+       let title = front_matter.title.clone().unwrap_or(markdown.title);
+       let summary = front_matter.description.clone().unwrap_or(markdown.summary);
+       let summary_html = markdown.summary_html.unwrap_or_else(|| {
+           let mut html = String::from("<p>");
+           escape_html(&mut html, &summary);
+           html.push_str("</p>");
+           html
+       });
 
-    for key in THEME_SET.themes.keys() {
-        println!("Loaded theme: {}", key);
+       rendered.push(RenderedPost {
+           url,
+           title,
+           date: front_matter.date.clone(),
+           published: front_matter.date.as_deref().and_then(|date| front_matter::parse_date(date).ok()),
+           summary,
+           summary_html,
+           content: markdown.body,
+           outline: markdown.outline,
+           stats: markdown.stats,
+           unpublished: unpublished.clone(),
+       });
     }
 
-    let theme_name = "dark";
-    let theme = THEME_SET.themes.get(theme_name).expect("Failed to load theme");
+    let sections = Sections::new(sources.iter().map(|((source_path, _, _), _)| *source_path));
 
-    // Generate the CSS string for the theme
-    let css_string = generate_css_for_theme(theme);
+    let front_matters: Vec<_> = sources.iter().map(|((_, front_matter, _), _)| front_matter).collect();
+    let (taxonomies, mut problems) = taxonomies::collect(&config.taxonomies, &front_matters);
+    let (all_series, series_problems) = series::collect(&front_matters);
+    problems.extend(series_problems);
+    for (i, msg) in problems {
+        eprintln!("warning: {}: {msg}", posts[i].0.display());
+    }
 
-    // Path to your posts.css
-    let posts_css = PathBuf::from("templates/posts.css");
-           
-    // Append the generated theme CSS to posts.css
-    append_css_to_file(&css_string, &posts_css)?;
+    // Neighbours and related posts need every post, so are found before rendering any
+    let features: Vec<_> = rendered.iter().enumerate().map(|(i, post)| related::Features {
+        date: post.published,
+        terms: taxonomies.iter()
+            .flat_map(|taxonomy| taxonomy.terms_of(i).map(|term| format!("{}/{}", taxonomy.slug, term.slug)))
+            .collect(),
+        summary: &post.summary,
+        is_post: sections.of_index(i).is_none() && post.url != "index.html",
+    }).collect();
+    let neighbours = related::chronological(&features);
+    let related_posts = related::related(&features, config.related_posts);
+
+    let listings = Listings { handlebars: &handlebars, config, output_dir: &output_dir, rendered: &rendered };
+    for (i, post) in rendered.iter().enumerate() {
+       let post_data = |url: &str| {
+           let mut markdown_data = page_data(config, url);
+           extend(&mut markdown_data, serde_json::json!({
+            "title": post.title,
+            "date": post.date,
+            "content": post.content,
+            "summary": post.summary,
+            "summary_html": post.summary_html,
+            "outline": post.outline,
+            "stats": post.stats,
+            "draft": post.unpublished,
+            "terms": post_terms(&taxonomies, i, url),
+            "series": post_series(&all_series, i, &rendered, url),
+            "previous": neighbours[i].0.map(|older| rendered[older].listing_entry(url)),
+            "next": neighbours[i].1.map(|newer| rendered[newer].listing_entry(url)),
+            "related": related_posts[i].iter().map(|&j| rendered[j].listing_entry(url)).collect::<Vec<_>>(),
+           }));
+           markdown_data
+       };
+
+       let section = sections.of_index(i);
+       let (source_path, front_matter, _) = &sources[i].0;
+       // Pages use the `page_template` of the closest section setting one
+       let template = front_matter.template.as_deref()
+           .or_else(|| section.is_none().then(|| sections.page_template(source_path, &front_matters)).flatten())
+           .unwrap_or(if section.is_some() { "section" } else { "posts" });
+       if !page_templates.contains(template) {
+           return Err(io::Error::new(
+               io::ErrorKind::NotFound,
+               format!("{}: no template named {template} in templates/", posts[i].0.display()),
+           ));
+       }
+
+       if let Some(section) = section {
+           let written = listings.write(template, &post.url, &section.pages, |url, number| {
+               let mut data = post_data(url);
+               // The content was rendered for the first page, and its relative
+               // links would break on the others
+               if number > 1 {
+                   data["content"] = "".into();
+                   data["outline"] = "".into();
+               }
+               data["subsections"] = section
+                   .subsections
+                   .iter()
+                   .filter_map(|dir| sections.get(dir))
+                   .map(|subsection| subsection_entry(subsection, &rendered, url))
+                   .collect::<Vec<_>>()
+                   .into();
+               data
+           })?;
+           for url in written {
+               outputs.record(source_path, url);
+           }
+           continue;
+       }
+
+       let rendered_html = handlebars.render(template, &post_data(&post.url))
+        .map_err(io::Error::other)?;
+
+        write_if_changed(&output_dir.join(&post.url), &rendered_html)?;
+    }
 
+    for series in &all_series {
+        let url = series.url();
+        check_free(&url, &rendered)?;
+
+        let posts: Vec<_> = series.posts.iter().enumerate().map(|(part, &i)| {
+            let mut entry = rendered[i].listing_entry(&url);
+            entry["part"] = (part + 1).into();
+            entry
+        }).collect();
+        let mut data = page_data(config, &url);
+        extend(&mut data, serde_json::json!({
+            "title": series.name,
+            "pages": posts,
+        }));
+        let html = handlebars.render("series", &data).map_err(io::Error::other)?;
+        write_if_changed(&output_dir.join(&url), &html)?;
+    }
 
-    let output_dir = PathBuf::from("blog");
+    for taxonomy in &taxonomies {
+        let url = taxonomy.url();
+        check_free(&url, &rendered)?;
+
+        let terms: Vec<_> = taxonomy.terms.iter().map(|term| serde_json::json!({
+            "name": term.name,
+            "url": urls::relative(&url, &taxonomy.term_url(term)),
+            "page_count": term.pages.len(),
+        })).collect();
+        let mut data = page_data(config, &url);
+        extend(&mut data, serde_json::json!({
+            "title": taxonomy.name,
+            "terms": terms,
+        }));
+        let html = handlebars.render("taxonomy", &data).map_err(io::Error::other)?;
+        write_if_changed(&output_dir.join(&url), &html)?;
+
+        for term in &taxonomy.terms {
+            let url = taxonomy.term_url(term);
+            check_free(&url, &rendered)?;
+            let feed_url = taxonomy.feed_url(term);
+
+            listings.write("taxonomy_term", &url, &term.pages, |page_url, _| {
+                let mut data = page_data(config, page_url);
+                extend(&mut data, serde_json::json!({
+                    "title": term.name,
+                    "taxonomy": taxonomy.name,
+                    "taxonomy_url": urls::relative(page_url, &taxonomy.url()),
+                    "feed": urls::relative(page_url, &feed_url),
+                }));
+                data
+            })?;
+
+            // Feeds never have unpublished posts, which they could not mark as such
+            let entries: Vec<_> = newest_first(&term.pages, &rendered)
+                .into_iter()
+                .filter(|post| post.unpublished.is_none())
+                .map(|post| feeds::Entry {
+                    title: &post.title,
+                    url: absolute_url(config, &post.url),
+                    date: post.published,
+                    summary_html: urls::rebase(&post.summary_html, &post.url, &format!("{}/", config.base_url.trim_end_matches('/'))),
+                })
+                .collect();
+            let feed = feeds::atom(
+                &format!("{}: {}", taxonomy.name, term.name),
+                &absolute_url(config, &feed_url),
+                &absolute_url(config, &url),
+                &entries,
+            );
+            write_if_changed(&output_dir.join(&feed_url), &feed)?;
+        }
+    }
 
-    match fs::create_dir_all(&output_dir) {
-        Ok(dir) => dir,
-        Err(error) => return  Err(error),
+    images.remove_unused(&config.static_dir)?;
+    outputs.save()?;
+
+    // Links inside shortcodes are collected apart from the others,
+    // so broken links are sorted to be reported in reading order
+    let mut broken_links = links::check(&pages, &output_dir, &config.external_links)?;
+    broken_links.sort_by(|(a, a_diagnostic), (b, b_diagnostic)| {
+        (&posts[*a].0, a_diagnostic.offset).cmp(&(&posts[*b].0, b_diagnostic.offset))
+    });
+    for (page, diagnostic) in broken_links {
+        let (path, content) = &posts[page];
+        let (line, column) = diagnostic.location(content);
+        eprintln!("warning: {}:{line}:{column}: {}", path.display(), diagnostic.message);
     }
 
+    if !skipped.is_empty() {
+        println!("Skipped {} unpublished posts, build with --drafts to include them:", skipped.len());
+        for (path, reason) in &skipped {
+            println!("  {}: {reason}", path.display());
+        }
+    }
+    let drafts: Vec<_> = posts.iter().zip(&sources)
+        .filter_map(|((path, _), (_, unpublished))| Some((path, unpublished.as_ref()?)))
+        .collect();
+    if !drafts.is_empty() {
+        println!("Included {} unpublished posts:", drafts.len());
+        for (path, reason) in drafts {
+            println!("  {}: {reason}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Registers every `.hbs` file of `dir` as a template named after the file,
+/// like `posts` for `posts.hbs`, and those of `dir/partials` as partials.
+/// Also returns the names of the former, which pages may use.
+///
+/// Templates share a layout by defining its blocks as inline partials,
+/// then including it, like `{{#*inline "content"}}...{{/inline}}{{> base}}`.
+fn load_templates(dir: &Path) -> Result<(handlebars::Handlebars<'static>, HashSet<String>), io::Error> {
     let mut handlebars = handlebars::Handlebars::new();
-        handlebars.register_template_file("template", "templates/posts.hbs")
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let mut pages = HashSet::new();
 
+    for (dir, is_partial) in [(dir.join("partials"), true), (dir.to_owned(), false)] {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("hbs") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let registered = if is_partial {
+                handlebars.register_partial(name, fs::read_to_string(&path)?)
+            } else {
+                pages.insert(name.to_owned());
+                handlebars.register_template_file(name, &path)
+            };
+            registered.map_err(|err| io::Error::other(format!("{}: {err}", path.display())))?;
+        }
+    }
 
-    let blog_posts_css_path = output_dir.join("posts.css");
-    fs::copy(&posts_css, &blog_posts_css_path)?;
+    Ok((handlebars, pages))
+}
 
+/// What every page gets in its template data, for the page at `url`
+fn page_data(config: &Config, url: &str) -> serde_json::Value {
+    // Assets are linked relatively, which works both when served and
+    // when opening the files directly
+    let root = urls::root(url);
+    serde_json::json!({
+        "home": urls::relative(url, "index.html"),
+        "posts_css": format!("{root}posts.css"),
+        "copy_code_script": config.copy_code_button.then(|| format!("{root}copy-code.js")),
+    })
+}
 
-    for (path, content) in posts {
-       let markdown = parse(&content);
+/// Adds the fields of the `fields` object to the `data` object
+fn extend(data: &mut serde_json::Value, fields: serde_json::Value) {
+    if let (Some(data), serde_json::Value::Object(fields)) = (data.as_object_mut(), fields) {
+        data.extend(fields);
+    }
+}
 
+/// The URL of an output path once published, like `https://example.com/tags/rust/`
+fn absolute_url(config: &Config, output_path: &str) -> String {
+    format!("{}/{}", config.base_url.trim_end_matches('/'), urls::href_of(output_path))
+}
 
-       let markdown_data = serde_json::json!({
-        "posts_css": "posts.css",
-        "title": markdown.title,
-        "content": markdown.body,
-        "summary": markdown.summary,
-        //"outline": markdown.outline
-       });
+/// Listing pages live next to posts, so must not take the URL of one
+fn check_free(url: &str, rendered: &[RenderedPost]) -> Result<(), io::Error> {
+    match rendered.iter().find(|post| post.url == url) {
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("a post and a listing would both be written to {url}"),
+        )),
+        None => Ok(()),
+    }
+}
 
+/// The terms of the post at `index` by taxonomy, linked from the page at `from`
+fn post_terms(taxonomies: &[Taxonomy], index: usize, from: &str) -> serde_json::Value {
+    taxonomies.iter().map(|taxonomy| {
+        let terms: Vec<_> = taxonomy.terms_of(index).map(|term| serde_json::json!({
+            "name": term.name,
+            "url": urls::relative(from, &taxonomy.term_url(term)),
+        })).collect();
+        (taxonomy.name.clone(), serde_json::Value::from(terms))
+    }).collect::<serde_json::Map<_, _>>().into()
+}
+
+/// The series of the post at `index`, with the other posts in reading order,
+/// linked from the page at `from`
+fn post_series(all_series: &[Series], index: usize, rendered: &[RenderedPost], from: &str) -> serde_json::Value {
+    let Some((series, position)) = all_series
+        .iter()
+        .find_map(|series| Some((series, series.position(index)?)))
+    else {
+        return serde_json::Value::Null;
+    };
+
+    let link = |n: usize| {
+        let post = &rendered[series.posts[n]];
+        serde_json::json!({
+            "title": post.title,
+            "url": urls::relative(from, &post.url),
+            "part": n + 1,
+            "current": n == position,
+        })
+    };
+    let posts: Vec<_> = (0..series.posts.len()).map(link).collect();
+
+    serde_json::json!({
+        "name": series.name,
+        "url": urls::relative(from, &series.url()),
+        "part": position + 1,
+        "total": series.posts.len(),
+        "posts": posts,
+        "prev": position.checked_sub(1).map(link),
+        "next": (position + 1 < series.posts.len()).then(|| link(position + 1)),
+    })
+}
 
-       let rendered_html = handlebars.render("template", &markdown_data)
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+/// A post with its markdown rendered, before applying its template
+struct RenderedPost {
+    /// Output path, like `notes/rust/foo.html`
+    url: String,
+    title: String,
+    date: Option<String>,
+    published: Option<DateTime<Utc>>,
+    summary: String,
+    summary_html: String,
+    content: String,
+    outline: String,
+    stats: Stats,
+    /// Why the post is not published, when building drafts
+    unpublished: Option<String>,
+}
 
-        let file_name = path.file_stem()
-            .and_then(|stem| stem.to_str())
-            .unwrap_or("");
-        
-        let output_file = output_dir.join(format!("{}.html", file_name));
-        fs::write(&output_file, rendered_html)?;
+impl RenderedPost {
+    /// What listings show of the post, linked from the page at `from`
+    fn listing_entry(&self, from: &str) -> serde_json::Value {
+        serde_json::json!({
+            "title": self.title,
+            "url": urls::relative(from, &self.url),
+            "date": self.date,
+            "summary": self.summary,
+            // Its links are relative to the post
+            "summary_html": urls::rebase(&self.summary_html, &self.url, &urls::root(from)),
+            "stats": self.stats,
+            "draft": self.unpublished,
+        })
     }
+}
 
-    Ok(())
+/// What writing listings needs, the same for every listing of a build
+struct Listings<'a> {
+    handlebars: &'a handlebars::Handlebars<'a>,
+    config: &'a Config,
+    output_dir: &'a Path,
+    rendered: &'a [RenderedPost],
+}
+
+impl Listings<'_> {
+    /// Writes a listing of the posts at `pages` with `template`, split into pages
+    /// of `Config::paginate_by` posts, the first one at `canonical`,
+    /// and returns the output paths written.
+    ///
+    /// `data` gives the rest of the template data for the page at a URL,
+    /// by page number. Templates get the posts of the page as `pages`,
+    /// and links to the other pages in `paginator`.
+    fn write(
+        &self,
+        template: &str,
+        canonical: &str,
+        pages: &[usize],
+        data: impl Fn(&str, usize) -> serde_json::Value,
+    ) -> Result<Vec<String>, io::Error> {
+        let posts = newest_first(pages, self.rendered);
+        let paginator = Paginator::new(canonical, posts.len(), self.config.paginate_by);
+        let mut written = Vec::new();
+
+        for number in 1..=paginator.total_pages() {
+            let url = paginator.url(number);
+            if number > 1 {
+                check_free(&url, self.rendered)?;
+            }
+
+            let mut page_data = data(&url, number);
+            page_data["pages"] = posts[paginator.items(number)]
+                .iter()
+                .map(|post| post.listing_entry(&url))
+                .collect::<Vec<_>>()
+                .into();
+            page_data["paginator"] = paginator.data(number);
+
+            let html = self.handlebars.render(template, &page_data).map_err(io::Error::other)?;
+            write_if_changed(&self.output_dir.join(&url), &html)?;
+            written.push(url);
+        }
+
+        // Page 1 lives at the canonical URL, but people do try `page/1/`
+        let redirect = paginator.redirect_url();
+        let html = paginator::redirect_html(&redirect, canonical, &absolute_url(self.config, canonical));
+        write_if_changed(&self.output_dir.join(&redirect), html)?;
+        written.push(redirect);
+        Ok(written)
+    }
+}
+
+/// The posts at `pages`, newest first
+fn newest_first<'a>(pages: &[usize], rendered: &'a [RenderedPost]) -> Vec<&'a RenderedPost> {
+    let mut posts: Vec<&RenderedPost> = pages.iter().map(|&i| &rendered[i]).collect();
+    // Undated posts go last
+    posts.sort_by(|a, b| b.published.is_some().cmp(&a.published.is_some())
+        .then_with(|| b.published.cmp(&a.published))
+        .then_with(|| a.title.cmp(&b.title)));
+    posts
+}
+
+/// The posts at `pages`, newest first, as listed on the page at `from`
+fn listing(pages: &[usize], rendered: &[RenderedPost], from: &str) -> Vec<serde_json::Value> {
+    newest_first(pages, rendered).iter().map(|post| post.listing_entry(from)).collect()
+}
+
+/// A subsection as listed on the page at `from`, linked only if it has an `_index.md`
+fn subsection_entry(section: &Section, rendered: &[RenderedPost], from: &str) -> serde_json::Value {
+    let index = section.index.map(|i| &rendered[i]);
+    let name = section.dir.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let pages = listing(&section.pages, rendered, from);
+    serde_json::json!({
+        "title": index.map_or(name, |index| &index.title),
+        "url": index.map(|index| urls::relative(from, &index.url)),
+        "page_count": pages.len(),
+        "pages": pages,
+    })
 }
 
 
@@ -722,4 +2021,50 @@ pub(crate) fn generate_html(posts: &[(PathBuf, String)]) -> Result<(), io::Error
 //     }
 
 //     Ok(())
-// }
\ No newline at end of file
+// }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_summaries() {
+        let cases = [
+            ("Short enough", 20, "Short enough"),
+            ("Exactly ten", 11, "Exactly ten"),
+            ("Cut between words here", 13, "Cut between…"),
+            ("Cut inside a word", 10, "Cut inside…"),
+            ("Cut inside a word", 8, "Cut…"),
+            ("Trailing punctuation, dropped", 22, "Trailing punctuation…"),
+            ("Unbreakable", 4, "Unbr…"),
+            ("Ünïcödé characters", 7, "Ünïcödé…"),
+        ];
+        for (text, limit, summary) in cases {
+            assert_eq!(truncate_words(text, limit), summary, "{text:?} at {limit}");
+        }
+    }
+
+    #[test]
+    fn summary_links_work_from_taxonomy_pages() {
+        let post = RenderedPost {
+            url: "notes/rust/foo.html".to_owned(),
+            title: "Foo".to_owned(),
+            date: None,
+            published: None,
+            summary: "See bar".to_owned(),
+            summary_html: "<p>See <a href='bar.html'>bar</a></p><img src='../../images/pic-1.png'>".to_owned(),
+            content: String::new(),
+            outline: String::new(),
+            stats: Stats::default(),
+            unpublished: None,
+        };
+
+        let entry = post.listing_entry("tags/rust/index.html");
+        assert_eq!(entry["url"], "../../notes/rust/foo.html");
+        assert_eq!(
+            entry["summary_html"],
+            "<p>See <a href='../../notes/rust/bar.html'>bar</a></p><img src='../../images/pic-1.png'>",
+        );
+    }
+}
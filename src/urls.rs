@@ -0,0 +1,354 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use crate::front_matter::FrontMatter;
+
+
+/// Where each post ends up in the output directory, so that posts
+/// can link to each other by their source path.
+pub(crate) struct Urls {
+    /// Output path of each post, like `about.html` or `about/index.html`
+    /// with pretty URLs, keyed by source path relative to the content directory
+    by_source: HashMap<PathBuf, String>,
+    /// Posts which are not built, that links must not point to
+    unpublished: HashSet<PathBuf>,
+}
+
+impl Urls {
+    /// `posts` are source paths relative to the content directory.
+    /// Posts are named after their `slug`, or their file name by default.
+    /// Bundles are always written to `slug/index.html`, named after their folder,
+    /// and the `_index.md` of sections to the `index.html` of their folder.
+    pub(crate) fn new<'a>(
+        posts: impl IntoIterator<Item = (&'a Path, &'a FrontMatter)>,
+        pretty_urls: bool,
+    ) -> Result<Self, io::Error> {
+        let mut by_source = HashMap::new();
+        let mut by_output: HashMap<String, &Path> = HashMap::new();
+
+        for (source, front_matter) in posts {
+            let stem = source.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+            if let Some(slug) = &front_matter.slug {
+                check_slug(slug).map_err(|msg| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {msg}", source.display()))
+                })?;
+            }
+            let slug = front_matter.slug.as_deref().unwrap_or(stem);
+            let dir = source.parent().and_then(|dir| dir.to_str()).unwrap_or("");
+            let dir = if dir.is_empty() { String::new() } else { format!("{dir}/") };
+
+            let output = if stem == "_index" {
+                // Section pages are named after their folder
+                format!("{dir}index.html")
+            } else if stem == "index" && front_matter.slug.is_none() {
+                format!("{dir}index.html")
+            } else if let Some(bundle) = bundle_dir(source) {
+                // Bundles keep their own folder, for relative links to their files
+                let parent = bundle.parent().and_then(|dir| dir.to_str()).unwrap_or("");
+                let parent = if parent.is_empty() { String::new() } else { format!("{parent}/") };
+                format!("{parent}{slug}/index.html")
+            } else if pretty_urls {
+                format!("{dir}{slug}/index.html")
+            } else {
+                format!("{dir}{slug}.html")
+            };
+
+            if let Some(other) = by_output.insert(output.clone(), source) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} and {} would both be written to {output}", other.display(), source.display()),
+                ));
+            }
+            by_source.insert(source.to_owned(), output);
+        }
+
+        Ok(Urls { by_source, unpublished: HashSet::new() })
+    }
+
+    /// Leaves the post at `source` out of the site, so that links to it are reported
+    pub(crate) fn unpublish(&mut self, source: &Path) {
+        self.by_source.remove(source);
+        self.unpublished.insert(source.to_owned());
+    }
+
+    /// Output path of the post at `source`
+    pub(crate) fn output_path(&self, source: &Path) -> Option<&str> {
+        self.by_source.get(source).map(String::as_str)
+    }
+
+    /// Resolves links to source files, like `./other.md#section` relative to
+    /// the post at `from`, or `@/other.md` relative to the content directory,
+    /// to a link to the page they become.
+    ///
+    /// Returns `None` for any other link, and an error for missing posts.
+    pub(crate) fn resolve(&self, from: &Path, href: &str) -> Option<Result<String, String>> {
+        let (path, fragment) = match href.find(['#', '?']) {
+            Some(i) => href.split_at(i),
+            None => (href, ""),
+        };
+
+        let source = if let Some(path) = path.strip_prefix("@/") {
+            normalize(Path::new(path))
+        } else if path.ends_with(".md") && !path.contains(':') && !path.starts_with('/') {
+            normalize(&from.parent().unwrap_or(Path::new("")).join(path))
+        } else {
+            return None;
+        };
+
+        let Some(target) = source.as_ref().and_then(|source| self.by_source.get(source)) else {
+            if source.is_some_and(|source| self.unpublished.contains(&source)) {
+                return Some(Err(format!("link to unpublished post {path}")));
+            }
+            return Some(Err(format!("link to missing post {path}")));
+        };
+        let from = self.output_path(from).unwrap_or("");
+
+        let mut link = relative(from, target);
+        link.push_str(fragment);
+        Some(Ok(link))
+    }
+}
+
+/// A slug names a single file or folder of the output directory,
+/// so must not be able to name anything else
+fn check_slug(slug: &str) -> Result<(), String> {
+    if slug.is_empty() || slug == "." || slug == ".." {
+        return Err(format!("invalid slug {slug:?}"));
+    }
+    if slug.contains(['/', '\\']) {
+        return Err(format!("invalid slug {slug:?}, slugs cannot contain slashes"));
+    }
+    if slug.starts_with('.') {
+        return Err(format!("invalid slug {slug:?}, slugs cannot start with a dot"));
+    }
+    Ok(())
+}
+
+/// The relative link from the page at `from` to the page at `to`,
+/// both output paths
+pub(crate) fn relative(from: &str, to: &str) -> String {
+    let link = format!("{}{}", root(from), href_of(to));
+    if link.is_empty() {
+        return "./".to_owned();
+    }
+    link
+}
+
+/// Rewrites the relative links of `html`, rendered for the page at `page`,
+/// for another page: each becomes `prefix` followed by its path from the root
+/// of the site, so `prefix` is `root()` of the other page, or the base URL
+/// followed by a slash for feeds.
+///
+/// Links with a scheme, or from the root like `/about/`, are left alone.
+pub(crate) fn rebase(html: &str, page: &str, prefix: &str) -> String {
+    static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"(\s(href|src|srcset)=)(?:'([^']*)'|"([^"]*)")"#).unwrap()
+    });
+
+    let rebase_link = |link: &str| match from_root(page, link) {
+        Some(path) if path.is_empty() && prefix.is_empty() => "./".to_owned(),
+        Some(path) => format!("{prefix}{path}"),
+        None => link.to_owned(),
+    };
+
+    ATTRIBUTE.replace_all(html, |caps: &Captures<'_>| {
+        let (quote, value) = match caps.get(3) {
+            Some(value) => ('\'', value.as_str()),
+            None => ('"', &caps[4]),
+        };
+        let value = if &caps[2] == "srcset" {
+            // Candidates are a URL, then an optional width or density
+            value.split(',')
+                .map(|candidate| {
+                    let candidate = candidate.trim_start();
+                    let (link, descriptor) = candidate.split_at(candidate.find(' ').unwrap_or(candidate.len()));
+                    format!("{}{descriptor}", rebase_link(link))
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            rebase_link(value)
+        };
+        format!("{}{quote}{value}{quote}", &caps[1])
+    }).into_owned()
+}
+
+/// The path from the root of the site that the relative `link` of the page
+/// at `page` points to, or `None` for links which are not relative
+fn from_root(page: &str, link: &str) -> Option<String> {
+    let has_scheme = link.split_once(':').is_some_and(|(scheme, _)| !scheme.contains(['/', '?', '#']));
+    if has_scheme || link.starts_with('/') {
+        return None;
+    }
+
+    let (path, rest) = link.split_at(link.find(['?', '#']).unwrap_or(link.len()));
+    if path.is_empty() {
+        return Some(format!("{}{rest}", href_of(page)));
+    }
+
+    let dir = &page[..page.rfind('/').map_or(0, |i| i + 1)];
+    let joined = format!("{dir}{path}");
+    let mut segments = Vec::new();
+    let mut parts = joined.split('/').peekable();
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." | "" if !last => {}
+            "." => segments.push(""),
+            ".." => {
+                segments.pop()?;
+                if last {
+                    segments.push("");
+                }
+            }
+            part => segments.push(part),
+        }
+    }
+    Some(segments.join("/") + rest)
+}
+
+/// The folder of a post bundle, a folder holding an `index.md` along with
+/// the files it links to, or `None` if `source` is not one
+pub(crate) fn bundle_dir(source: &Path) -> Option<&Path> {
+    let dir = source.parent().filter(|dir| !dir.as_os_str().is_empty())?;
+    (source.file_name()? == "index.md").then_some(dir)
+}
+
+/// How to link to an output path, without the `index.html` of pretty URLs
+pub(crate) fn href_of(output_path: &str) -> &str {
+    if output_path == "index.html" {
+        return "";
+    }
+    match output_path.strip_suffix("/index.html") {
+        // Keep the trailing slash
+        Some(dir) => &output_path[..dir.len() + 1],
+        None => output_path,
+    }
+}
+
+/// The relative link from a page to the root of the site, like `../`
+/// for `about/index.html`
+pub(crate) fn root(output_path: &str) -> String {
+    "../".repeat(output_path.matches('/').count())
+}
+
+/// Resolves `.` and `..` without touching the file system,
+/// or `None` if the path goes above the content directory.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(segment) => normalized.push(segment),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_links() {
+        let cases = [
+            ("index.html", "index.html", "./"),
+            ("index.html", "about.html", "about.html"),
+            ("index.html", "about/index.html", "about/"),
+            ("about/index.html", "index.html", "../"),
+            ("notes/rust/foo.html", "notes/bar/index.html", "../../notes/bar/"),
+            ("tags/rust/page/2/index.html", "tags/rust/index.html", "../../../../tags/rust/"),
+        ];
+        for (from, to, link) in cases {
+            assert_eq!(relative(from, to), link, "{from} to {to}");
+        }
+    }
+
+    #[test]
+    fn resolves_links_to_sources() {
+        let front_matter = FrontMatter::default();
+        let sources = ["index.md", "about.md", "notes/rust/foo.md", "notes/rust/bar.md", "notes/draft.md"];
+        let mut urls = Urls::new(sources.iter().map(|source| (Path::new(source), &front_matter)), true).unwrap();
+        urls.unpublish(Path::new("notes/draft.md"));
+
+        let foo = Path::new("notes/rust/foo.md");
+        let cases = [
+            ("bar.md", Some(Ok("../../../notes/rust/bar/"))),
+            ("./bar.md#usage", Some(Ok("../../../notes/rust/bar/#usage"))),
+            ("../../about.md?x=1", Some(Ok("../../../about/?x=1"))),
+            ("@/index.md", Some(Ok("../../../"))),
+            ("@/notes/rust/foo.md#top", Some(Ok("../../../notes/rust/foo/#top"))),
+            ("../draft.md", Some(Err("link to unpublished post ../draft.md"))),
+            ("missing.md", Some(Err("link to missing post missing.md"))),
+            ("../../../outside.md", Some(Err("link to missing post ../../../outside.md"))),
+            ("https://example.com/a.md", None),
+            ("/a.md", None),
+            ("bar.html", None),
+        ];
+        for (href, expected) in cases {
+            let expected = expected.map(|result| result.map(str::to_owned).map_err(str::to_owned));
+            assert_eq!(urls.resolve(foo, href), expected, "{href}");
+        }
+    }
+
+    #[test]
+    fn rebases_links_from_the_root() {
+        let page = "notes/rust/foo.html";
+        let cases = [
+            ("<a href='bar.html'>", "tags/rust/index.html", "<a href='../../notes/rust/bar.html'>"),
+            ("<a href='../'>", "tags/rust/index.html", "<a href='../../notes/'>"),
+            ("<a href='#intro'>", "index.html", "<a href='notes/rust/foo.html#intro'>"),
+            ("<a href='../../'>", "index.html", "<a href='./'>"),
+            ("<img src=\"../../images/pic.png\">", "index.html", "<img src=\"images/pic.png\">"),
+            ("<img srcset='a.png 480w, b.png 800w'>", "index.html", "<img srcset='notes/rust/a.png 480w, notes/rust/b.png 800w'>"),
+            ("<a href='https://example.com/'>", "index.html", "<a href='https://example.com/'>"),
+            ("<a href='mailto:me@example.com'>", "index.html", "<a href='mailto:me@example.com'>"),
+            ("<a href='/about/'>", "index.html", "<a href='/about/'>"),
+            ("<a href='../../../up.html'>", "index.html", "<a href='../../../up.html'>"),
+            ("<p>href='bar.html'</p>", "index.html", "<p>href='bar.html'</p>"),
+        ];
+        for (html, from, expected) in cases {
+            assert_eq!(rebase(html, page, &root(from)), expected, "{html} from {from}");
+        }
+
+        assert_eq!(
+            rebase("<a href='bar.html'>", page, "https://example.com/"),
+            "<a href='https://example.com/notes/rust/bar.html'>",
+        );
+    }
+
+    #[test]
+    fn rejects_slugs_outside_their_folder() {
+        let cases = [
+            ("about", true),
+            ("rust-1.70", true),
+            ("", false),
+            (".", false),
+            ("..", false),
+            ("../../src", false),
+            ("a/b", false),
+            ("a\\b", false),
+            (".hidden", false),
+        ];
+        for (slug, valid) in cases {
+            let front_matter = FrontMatter { slug: Some(slug.to_owned()), ..Default::default() };
+            let result = Urls::new([(Path::new("b/index.md"), &front_matter)], false);
+            assert_eq!(result.is_ok(), valid, "{slug:?}");
+            if let Err(err) = result {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                assert!(err.to_string().starts_with("b/index.md: "), "{err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+// Adds a button copying the code of each code block.
+// Included in posts when `copy_code_button` is enabled in config.yaml.
+document.querySelectorAll("figure.code").forEach((figure) => {
+    const code = figure.querySelector("pre code");
+    if (!code || !navigator.clipboard) {
+        return;
+    }
+
+    let header = figure.querySelector("figcaption.code-header");
+    if (!header) {
+        header = document.createElement("figcaption");
+        header.className = "code-header";
+        figure.prepend(header);
+    }
+
+    const button = document.createElement("button");
+    button.type = "button";
+    button.className = "copy-code";
+    button.textContent = "Copy";
+    button.addEventListener("click", async () => {
+        // The text content is the raw code: the markup of the highlighting
+        // is dropped, and line numbers are only drawn by the stylesheet
+        try {
+            await navigator.clipboard.writeText(code.textContent);
+            button.textContent = "Copied!";
+        } catch {
+            button.textContent = "Failed to copy";
+        }
+        setTimeout(() => (button.textContent = "Copy"), 2000);
+    });
+    header.append(button);
+});